@@ -0,0 +1,375 @@
+use crate::{InventoryError, Provider, Quantity, Supply};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Single row of an inventory file: one supply of one provider
+#[derive(Debug, Deserialize, Serialize)]
+struct InventoryRecord {
+    provider: String,
+    id: String,
+    name: String,
+    quantity: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InventoryFormat {
+    Csv,
+    Json,
+}
+
+impl InventoryFormat {
+    /// Tells the format by the file extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "csv" => Some(InventoryFormat::Csv),
+            "json" => Some(InventoryFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RowErrorKind {
+    /// The row could not be parsed at all
+    Malformed(String),
+    EmptyField(&'static str),
+    QuantityOutOfRange(i64),
+    Inventory(InventoryError),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RowError {
+    /// Line in the file the row starts at, counted from 1
+    pub line: u64,
+    pub kind: RowErrorKind,
+}
+
+impl Display for RowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.kind {
+            RowErrorKind::Malformed(message) => write!(f, "{}", message),
+            RowErrorKind::EmptyField(field) => write!(f, "{} must not be empty", field),
+            RowErrorKind::QuantityOutOfRange(quantity) => write!(
+                f,
+                "quantity {} is not within 0..={}",
+                quantity,
+                Quantity::MAX
+            ),
+            RowErrorKind::Inventory(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    UnknownFormat,
+    /// The file is not a list of rows
+    Malformed(String),
+    /// Every invalid row in the file
+    InvalidRows(Vec<RowError>),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(error) => write!(f, "{}", error),
+            ImportError::UnknownFormat => write!(f, "inventory file must be either .csv or .json"),
+            ImportError::Malformed(message) => write!(f, "{}", message),
+            ImportError::InvalidRows(errors) => {
+                let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+
+                write!(f, "invalid rows:\n{}", errors.join("\n"))
+            }
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(error: std::io::Error) -> Self {
+        ImportError::Io(error)
+    }
+}
+
+/// Reads provider inventories from a `.csv` or `.json` file
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Provider>, ImportError> {
+    let format = InventoryFormat::from_path(&path).ok_or(ImportError::UnknownFormat)?;
+    let reader = BufReader::new(File::open(path)?);
+
+    match format {
+        InventoryFormat::Csv => read_csv(reader),
+        InventoryFormat::Json => read_json(reader),
+    }
+}
+
+/// Writes provider inventories to a `.csv` or `.json` file
+pub fn save<P: AsRef<Path>>(path: P, providers: &[Provider]) -> std::io::Result<()> {
+    let format = InventoryFormat::from_path(&path).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            ImportError::UnknownFormat.to_string(),
+        )
+    })?;
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        InventoryFormat::Csv => write_csv(&mut writer, providers)?,
+        InventoryFormat::Json => write_json(&mut writer, providers)?,
+    }
+
+    writer.flush()
+}
+
+/// Reads rows with `provider,id,name,quantity` header
+pub fn read_csv<R: Read>(reader: R) -> Result<Vec<Provider>, ImportError> {
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let headers = csv_reader
+        .headers()
+        .map_err(|error| ImportError::Malformed(error.to_string()))?
+        .clone();
+
+    let rows = csv_reader.records().map(|row| {
+        let row = row.map_err(|error| RowError {
+            line: error
+                .position()
+                .map(|position| position.line())
+                .unwrap_or(0),
+            kind: RowErrorKind::Malformed(error.to_string()),
+        })?;
+        let line = row.position().map(|position| position.line()).unwrap_or(0);
+
+        row.deserialize::<InventoryRecord>(Some(&headers))
+            .map(|record| (line, record))
+            .map_err(|error| RowError {
+                line,
+                kind: RowErrorKind::Malformed(error.to_string()),
+            })
+    });
+
+    collect_providers(rows)
+}
+
+/// Reads an array of `{ "provider", "id", "name", "quantity" }` objects
+pub fn read_json<R: Read>(mut reader: R) -> Result<Vec<Provider>, ImportError> {
+    let mut json = String::new();
+    reader.read_to_string(&mut json)?;
+
+    let raw_rows: Vec<&RawValue> =
+        serde_json::from_str(&json).map_err(|error| ImportError::Malformed(error.to_string()))?;
+
+    let rows = raw_rows.into_iter().map(|raw_row| {
+        let line = line_of(&json, raw_row.get());
+
+        serde_json::from_str::<InventoryRecord>(raw_row.get())
+            .map(|record| (line, record))
+            .map_err(|error| RowError {
+                line,
+                kind: RowErrorKind::Malformed(error.to_string()),
+            })
+    });
+
+    collect_providers(rows)
+}
+
+pub fn write_csv<W: Write>(writer: W, providers: &[Provider]) -> std::io::Result<()> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+
+    for record in records(providers) {
+        csv_writer.serialize(record)?;
+    }
+
+    csv_writer.flush()
+}
+
+pub fn write_json<W: Write>(writer: W, providers: &[Provider]) -> std::io::Result<()> {
+    let records: Vec<InventoryRecord> = records(providers).collect();
+
+    serde_json::to_writer_pretty(writer, &records)?;
+
+    Ok(())
+}
+
+fn records(providers: &[Provider]) -> impl Iterator<Item = InventoryRecord> + '_ {
+    providers.iter().flat_map(|provider| {
+        provider
+            .inventory
+            .iter()
+            .map(move |supply| InventoryRecord {
+                provider: provider.name.clone(),
+                id: supply.id.clone(),
+                name: supply.name.clone(),
+                quantity: supply.quantity.into(),
+            })
+    })
+}
+
+/// Groups the rows by provider, keeping the order they come in
+fn collect_providers<I>(rows: I) -> Result<Vec<Provider>, ImportError>
+where
+    I: Iterator<Item = Result<(u64, InventoryRecord), RowError>>,
+{
+    let mut providers: Vec<Provider> = vec![];
+    let mut errors = vec![];
+
+    for row in rows {
+        let (line, record) = match row {
+            Ok(row) => row,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+
+        let supply = match validate(&record) {
+            Ok(supply) => supply,
+            Err(kind) => {
+                errors.push(RowError { line, kind });
+                continue;
+            }
+        };
+
+        let provider = match providers
+            .iter_mut()
+            .position(|provider| provider.name == record.provider)
+        {
+            Some(idx) => &mut providers[idx],
+            None => {
+                providers.push(Provider::new(&record.provider));
+                providers.last_mut().expect("provider was just added")
+            }
+        };
+
+        if let Err(error) = provider.add_supply(supply) {
+            errors.push(RowError {
+                line,
+                kind: RowErrorKind::Inventory(error),
+            });
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(providers),
+        false => Err(ImportError::InvalidRows(errors)),
+    }
+}
+
+fn validate(record: &InventoryRecord) -> Result<Supply, RowErrorKind> {
+    let fields = [
+        ("provider", &record.provider),
+        ("id", &record.id),
+        ("name", &record.name),
+    ];
+
+    if let Some((field, _)) = fields.iter().find(|(_, value)| value.trim().is_empty()) {
+        return Err(RowErrorKind::EmptyField(field));
+    }
+
+    let quantity = Quantity::try_from(record.quantity)
+        .map_err(|_| RowErrorKind::QuantityOutOfRange(record.quantity))?;
+
+    Ok(Supply::new(&record.id, &record.name, quantity))
+}
+
+/// Finds the line the `fragment` of the `text` starts at
+fn line_of(text: &str, fragment: &str) -> u64 {
+    let offset = fragment.as_ptr() as usize - text.as_ptr() as usize;
+
+    text[..offset].matches('\n').count() as u64 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_loads_the_same_inventories_from_csv_and_json() {
+        let from_csv = load("input/inventory.csv").unwrap();
+        let from_json = load("input/inventory.json").unwrap();
+
+        assert_eq!(from_csv, from_json);
+        assert_eq!(
+            from_csv
+                .iter()
+                .map(|provider| provider.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["4f", "nike", "adidas"]
+        );
+        assert_eq!(from_csv[2].supply("ad-3").unwrap().quantity, 444);
+    }
+
+    #[test]
+    fn it_reports_every_invalid_csv_row_with_its_line() {
+        let csv = "\
+provider,id,name,quantity
+4f,4f-1,Winter jacket,100
+4f,,Snowboard jacket,20
+nike,ni-1,Football shoes - pair,70000
+nike,ni-2,Football pads - pair,lots
+";
+
+        match read_csv(csv.as_bytes()) {
+            Err(ImportError::InvalidRows(errors)) => {
+                assert_eq!(
+                    errors.iter().map(|error| error.line).collect::<Vec<_>>(),
+                    vec![3, 4, 5]
+                );
+                assert_eq!(errors[0].kind, RowErrorKind::EmptyField("id"));
+                assert_eq!(errors[1].kind, RowErrorKind::QuantityOutOfRange(70000));
+                assert!(matches!(errors[2].kind, RowErrorKind::Malformed(_)));
+            }
+            result => panic!("expected invalid rows, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_reports_every_invalid_json_row_with_its_line() {
+        let json = r#"[
+  { "provider": "4f", "id": "4f-1", "name": "Winter jacket", "quantity": 65535 },
+  { "provider": "4f", "id": "4f-1", "name": "Winter jacket", "quantity": 1 },
+  {
+    "provider": "nike",
+    "id": "ni-1"
+  }
+]"#;
+
+        match read_json(json.as_bytes()) {
+            Err(ImportError::InvalidRows(errors)) => {
+                assert_eq!(
+                    errors.iter().map(|error| error.line).collect::<Vec<_>>(),
+                    vec![3, 4]
+                );
+                assert!(matches!(errors[0].kind, RowErrorKind::Inventory(_)));
+                assert!(matches!(errors[1].kind, RowErrorKind::Malformed(_)));
+            }
+            result => panic!("expected invalid rows, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_exports_inventories_back() {
+        let providers = load("input/inventory.csv").unwrap();
+
+        let mut csv = vec![];
+        write_csv(&mut csv, &providers).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            std::fs::read_to_string("input/inventory.csv").unwrap()
+        );
+
+        let mut json = vec![];
+        write_json(&mut json, &providers).unwrap();
+        assert_eq!(read_json(json.as_slice()).unwrap(), providers);
+    }
+}
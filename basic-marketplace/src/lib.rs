@@ -1,20 +1,295 @@
-#[derive(Debug)]
-struct Supply {
-    id: String,
-    name: String,
-    quantity: u16,
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::{Bound, RangeBounds};
+
+pub use crate::sampling::Sampling;
+
+pub mod io;
+mod sampling;
+
+pub type Quantity = u16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Supply {
+    pub id: String,
+    pub name: String,
+    pub quantity: Quantity,
+}
+
+impl Supply {
+    pub fn new(id: &str, name: &str, quantity: Quantity) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            quantity,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Provider {
+    pub name: String,
+    pub inventory: Vec<Supply>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InventoryError {
+    SupplyNotFound(String),
+    /// The stock would not fit into `Quantity`
+    Overflow {
+        id: String,
+        quantity: Quantity,
+        added: u32,
+    },
+    InsufficientStock {
+        id: String,
+        quantity: Quantity,
+        requested: u32,
+    },
+}
+
+impl Display for InventoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InventoryError::SupplyNotFound(id) => write!(f, "supply {} not found", id),
+            InventoryError::Overflow {
+                id,
+                quantity,
+                added,
+            } => write!(
+                f,
+                "cannot add {} items to {} items of supply {}",
+                added, quantity, id
+            ),
+            InventoryError::InsufficientStock {
+                id,
+                quantity,
+                requested,
+            } => write!(
+                f,
+                "cannot take {} items out of {} items of supply {}",
+                requested, quantity, id
+            ),
+        }
+    }
+}
+
+impl Error for InventoryError {}
+
+impl Provider {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            inventory: vec![],
+        }
+    }
+
+    pub fn supply(&self, id: &str) -> Option<&Supply> {
+        self.inventory.iter().find(|supply| supply.id == id)
+    }
+
+    /// Adds the supply to the inventory.
+    ///
+    /// When the inventory already holds a supply with the same id,
+    /// the quantity is added to it instead. Returns the resulting stock.
+    pub fn add_supply(&mut self, supply: Supply) -> Result<Quantity, InventoryError> {
+        match self.position(&supply.id) {
+            Ok(idx) => self.restock_at(idx, supply.quantity),
+            Err(_) => {
+                let quantity = supply.quantity;
+                self.inventory.push(supply);
+                Ok(quantity)
+            }
+        }
+    }
+
+    pub fn remove_supply(&mut self, id: &str) -> Option<Supply> {
+        let idx = self.position(id).ok()?;
+
+        Some(self.inventory.remove(idx))
+    }
+
+    pub fn restock(&mut self, id: &str, quantity: Quantity) -> Result<Quantity, InventoryError> {
+        let idx = self.position(id)?;
+
+        self.restock_at(idx, quantity)
+    }
+
+    pub fn withdraw(&mut self, id: &str, quantity: Quantity) -> Result<Quantity, InventoryError> {
+        let idx = self.position(id)?;
+        let supply = &mut self.inventory[idx];
+
+        supply.quantity = supply
+            .quantity
+            .checked_sub(quantity)
+            .ok_or_else(|| InventoryError::InsufficientStock {
+                id: supply.id.clone(),
+                quantity: supply.quantity,
+                requested: quantity.into(),
+            })?;
+
+        Ok(supply.quantity)
+    }
+
+    /// Changes the stock by `delta` items, either way
+    pub fn adjust_stock(&mut self, id: &str, delta: i32) -> Result<Quantity, InventoryError> {
+        let idx = self.position(id)?;
+        let supply = &mut self.inventory[idx];
+        let adjusted = i32::from(supply.quantity)
+            .checked_add(delta)
+            .and_then(|adjusted| Quantity::try_from(adjusted).ok());
+
+        supply.quantity = match adjusted {
+            Some(quantity) => quantity,
+            None if delta < 0 => {
+                return Err(InventoryError::InsufficientStock {
+                    id: supply.id.clone(),
+                    quantity: supply.quantity,
+                    requested: delta.unsigned_abs(),
+                })
+            }
+            None => {
+                return Err(InventoryError::Overflow {
+                    id: supply.id.clone(),
+                    quantity: supply.quantity,
+                    added: delta.unsigned_abs(),
+                })
+            }
+        };
+
+        Ok(supply.quantity)
+    }
+
+    /// Folds the supplies sharing an id into the first one of them.
+    ///
+    /// Leaves the inventory untouched when the merged stock would overflow.
+    pub fn merge_duplicates(&mut self) -> Result<usize, InventoryError> {
+        let mut merged: Vec<Supply> = Vec::with_capacity(self.inventory.len());
+
+        for supply in &self.inventory {
+            match merged.iter_mut().find(|item| item.id == supply.id) {
+                None => merged.push(supply.clone()),
+                Some(item) => {
+                    item.quantity = item.quantity.checked_add(supply.quantity).ok_or_else(|| {
+                        InventoryError::Overflow {
+                            id: item.id.clone(),
+                            quantity: item.quantity,
+                            added: supply.quantity.into(),
+                        }
+                    })?
+                }
+            }
+        }
+
+        let merged_count = self.inventory.len() - merged.len();
+        self.inventory = merged;
+
+        Ok(merged_count)
+    }
+
+    fn position(&self, id: &str) -> Result<usize, InventoryError> {
+        self.inventory
+            .iter()
+            .position(|supply| supply.id == id)
+            .ok_or_else(|| InventoryError::SupplyNotFound(id.into()))
+    }
+
+    fn restock_at(&mut self, idx: usize, quantity: Quantity) -> Result<Quantity, InventoryError> {
+        let supply = &mut self.inventory[idx];
+
+        supply.quantity = supply
+            .quantity
+            .checked_add(quantity)
+            .ok_or_else(|| InventoryError::Overflow {
+                id: supply.id.clone(),
+                quantity: supply.quantity,
+                added: quantity.into(),
+            })?;
+
+        Ok(supply.quantity)
+    }
+}
+
+/// A supply in the aggregated inventory, along with its provider
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InventoryItem<'a> {
+    pub provider: &'a str,
+    pub supply: &'a Supply,
 }
 
-struct Provider {
-    name: String,
-    inventory: Vec<Supply>,
+/// Read-only view over the inventories of many providers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AggregatedInventory<'a> {
+    items: Vec<InventoryItem<'a>>,
+}
+
+impl<'a> AggregatedInventory<'a> {
+    /// Aggregates the inventories, keeping the order of the providers and their supplies
+    pub fn new<I: IntoIterator<Item = &'a Provider>>(providers: I) -> Self {
+        let items = providers
+            .into_iter()
+            .flat_map(|provider| {
+                provider.inventory.iter().map(move |supply| InventoryItem {
+                    provider: &provider.name,
+                    supply,
+                })
+            })
+            .collect();
+
+        Self { items }
+    }
+
+    pub fn items(&self) -> &[InventoryItem<'a>] {
+        &self.items
+    }
+
+    pub fn supplies(&self) -> impl Iterator<Item = &'a Supply> + '_ {
+        self.items.iter().map(|item| item.supply)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the items within the range, cut down to the items available
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> &[InventoryItem<'a>] {
+        let len = self.items.len();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+        let start = start.min(len);
+
+        &self.items[start..end.clamp(start, len)]
+    }
+
+    pub fn find(&self, id: &str) -> impl Iterator<Item = &InventoryItem<'a>> + '_ {
+        let id = id.to_owned();
+
+        self.items.iter().filter(move |item| item.supply.id == id)
+    }
+
+    /// Stock of the supply summed up across all providers
+    pub fn total_quantity(&self, id: &str) -> u32 {
+        self.find(id).map(|item| u32::from(item.supply.quantity)).sum()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::HashMap;
-    use rand::seq::SliceRandom;
     use std::convert::TryInto;
 
     #[test]
@@ -90,14 +365,99 @@ mod tests {
         // the markerters will make sure the supply-side is easily available to the demand-side
         let marketer = ();
 
-        let aggregated_inventory: Vec<&Supply> = all_providers.into_iter()
-            .flat_map(|(k, v)| v.inventory.iter())
-            .collect();
+        let aggregated_inventory = AggregatedInventory::new(all_providers.into_values());
 
-        // TODO: use choose_multiple to pick random items to market
-        // let mut rng =rand::thread_rng();
-        let choice: &[&Supply] = &aggregated_inventory[2..=5];
+        assert_eq!(aggregated_inventory.len(), 7);
+        assert_eq!(aggregated_inventory.total_quantity("ad-3"), 444);
+
+        let mut rng = rand::thread_rng();
+        let choice: Vec<InventoryItem> = aggregated_inventory.sample(&mut rng, 4, Sampling::Uniform);
+
+        assert_eq!(choice.len(), 4);
 
         println!("Item picked for marketing: {:?}", choice);
     }
+
+    #[test]
+    fn it_merges_stock_of_supplies_with_same_id() {
+        let mut provider = Provider::new("4f");
+
+        assert_eq!(provider.add_supply(Supply::new("4f-1", "Winter jacket", 100)), Ok(100));
+        assert_eq!(provider.add_supply(Supply::new("4f-1", "Winter jacket", 20)), Ok(120));
+        assert_eq!(provider.inventory.len(), 1);
+
+        provider.inventory.push(Supply::new("4f-2", "Snowboard jacket", 5));
+        provider.inventory.push(Supply::new("4f-2", "Snowboard jacket", 7));
+
+        assert_eq!(provider.merge_duplicates(), Ok(1));
+        assert_eq!(provider.supply("4f-2").map(|supply| supply.quantity), Some(12));
+    }
+
+    #[test]
+    fn it_adjusts_stock_without_overflowing() {
+        let mut provider = Provider::new("nike");
+        provider.add_supply(Supply::new("ni-1", "Football shoes - pair", u16::MAX - 1)).unwrap();
+
+        assert_eq!(provider.restock("ni-1", 1), Ok(u16::MAX));
+        assert_eq!(
+            provider.restock("ni-1", 1),
+            Err(InventoryError::Overflow {
+                id: "ni-1".into(),
+                quantity: u16::MAX,
+                added: 1
+            })
+        );
+        assert_eq!(
+            provider.adjust_stock("ni-1", i32::MAX),
+            Err(InventoryError::Overflow {
+                id: "ni-1".into(),
+                quantity: u16::MAX,
+                added: i32::MAX as u32
+            })
+        );
+        assert_eq!(provider.adjust_stock("ni-1", -(u16::MAX as i32)), Ok(0));
+        assert_eq!(
+            provider.adjust_stock("ni-1", i32::MIN),
+            Err(InventoryError::InsufficientStock {
+                id: "ni-1".into(),
+                quantity: 0,
+                requested: 1 << 31
+            })
+        );
+        assert_eq!(
+            provider.withdraw("ni-1", 1),
+            Err(InventoryError::InsufficientStock {
+                id: "ni-1".into(),
+                quantity: 0,
+                requested: 1
+            })
+        );
+        assert_eq!(
+            provider.adjust_stock("ni-2", 1),
+            Err(InventoryError::SupplyNotFound("ni-2".into()))
+        );
+
+        provider.inventory.push(Supply::new("ni-2", "Football pads - pair", u16::MAX));
+
+        // nothing changes when merged stock would not fit
+        provider.add_supply(Supply::new("ni-3", "Football socks - pair", 1)).unwrap();
+        provider.inventory.push(Supply::new("ni-2", "Football pads - pair", 1));
+
+        assert!(provider.merge_duplicates().is_err());
+        assert_eq!(provider.inventory.len(), 4);
+        assert_eq!(provider.remove_supply("ni-3").map(|supply| supply.quantity), Some(1));
+    }
+
+    #[test]
+    fn it_slices_aggregated_inventory_of_any_size() {
+        let provider = Provider {
+            name: "adidas".into(),
+            inventory: vec![Supply::new("ad-1", "Tracksuite top", 50)],
+        };
+        let aggregated_inventory = AggregatedInventory::new(vec![&provider]);
+
+        assert_eq!(aggregated_inventory.slice(2..=5).len(), 0);
+        assert_eq!(aggregated_inventory.slice(..=5).len(), 1);
+        assert_eq!(aggregated_inventory.items()[0].provider, "adidas");
+    }
 }
@@ -0,0 +1,200 @@
+use crate::{AggregatedInventory, InventoryItem};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// How the items get picked from the aggregated inventory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    /// Every item is equally likely to be picked
+    Uniform,
+    /// Items with more stock are more likely to be picked
+    WeightedByQuantity,
+    /// Every provider gets its share of the picked items, as equal as their inventories allow
+    StratifiedByProvider,
+}
+
+impl<'a> AggregatedInventory<'a> {
+    /// Picks up to `amount` distinct items, never more than the inventory holds, nor any item out of stock
+    pub fn sample<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        amount: usize,
+        sampling: Sampling,
+    ) -> Vec<InventoryItem<'a>> {
+        let in_stock: Vec<InventoryItem<'a>> = self
+            .items()
+            .iter()
+            .filter(|item| item.supply.quantity > 0)
+            .copied()
+            .collect();
+
+        match sampling {
+            Sampling::Uniform => in_stock.choose_multiple(rng, amount).copied().collect(),
+            Sampling::WeightedByQuantity => in_stock
+                .choose_multiple_weighted(rng, amount, |item| f64::from(item.supply.quantity))
+                .map(|choice| choice.copied().collect())
+                .unwrap_or_default(),
+            Sampling::StratifiedByProvider => Self::sample_stratified(&in_stock, rng, amount),
+        }
+    }
+
+    /// Same as `sample`, but the same seed always gives the same selection, on every platform
+    pub fn sample_seeded(
+        &self,
+        seed: u64,
+        amount: usize,
+        sampling: Sampling,
+    ) -> Vec<InventoryItem<'a>> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        self.sample(&mut rng, amount, sampling)
+    }
+
+    fn sample_stratified<R: Rng + ?Sized>(
+        items: &[InventoryItem<'a>],
+        rng: &mut R,
+        amount: usize,
+    ) -> Vec<InventoryItem<'a>> {
+        // group the items by provider, keeping the providers' order
+        let mut strata: Vec<Vec<InventoryItem<'a>>> = vec![];
+
+        for item in items {
+            match strata
+                .iter_mut()
+                .find(|stratum| stratum[0].provider == item.provider)
+            {
+                Some(stratum) => stratum.push(*item),
+                None => strata.push(vec![*item]),
+            }
+        }
+
+        for stratum in strata.iter_mut() {
+            stratum.shuffle(rng);
+        }
+
+        // the providers take turns in a random order, so the leftover picks are not biased
+        strata.shuffle(rng);
+
+        let mut selection = Vec::with_capacity(amount.min(items.len()));
+        let mut round = 0;
+
+        while selection.len() < amount && selection.len() < items.len() {
+            for stratum in strata.iter() {
+                if selection.len() == amount {
+                    break;
+                }
+
+                if let Some(item) = stratum.get(round) {
+                    selection.push(*item);
+                }
+            }
+
+            round += 1;
+        }
+
+        selection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Provider, Supply};
+
+    fn providers() -> Vec<Provider> {
+        vec![
+            Provider {
+                name: "4f".into(),
+                inventory: vec![
+                    Supply::new("4f-1", "Winter jacket", 100),
+                    Supply::new("4f-2", "Snowboard jacket", 0),
+                ],
+            },
+            Provider {
+                name: "nike".into(),
+                inventory: vec![
+                    Supply::new("ni-1", "Football shoes - pair", 100),
+                    Supply::new("ni-2", "Football pads - pair", 200),
+                ],
+            },
+            Provider {
+                name: "adidas".into(),
+                inventory: vec![
+                    Supply::new("ad-1", "Tracksuite top", 50),
+                    Supply::new("ad-2", "Tracksuite bottom", 40),
+                    Supply::new("ad-3", "Socks - pair", 444),
+                ],
+            },
+        ]
+    }
+
+    #[test]
+    fn it_never_picks_more_items_than_available() {
+        let providers = providers();
+        let aggregated_inventory = AggregatedInventory::new(&providers);
+
+        for sampling in [
+            Sampling::Uniform,
+            Sampling::WeightedByQuantity,
+            Sampling::StratifiedByProvider,
+        ] {
+            assert_eq!(aggregated_inventory.sample_seeded(1, 3, sampling).len(), 3);
+            assert!(aggregated_inventory.sample_seeded(1, 100, sampling).len() <= 7);
+            assert!(AggregatedInventory::default()
+                .sample_seeded(1, 4, sampling)
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn it_reproduces_selection_for_same_seed() {
+        let providers = providers();
+        let aggregated_inventory = AggregatedInventory::new(&providers);
+
+        assert_eq!(
+            aggregated_inventory.sample_seeded(7, 4, Sampling::WeightedByQuantity),
+            aggregated_inventory.sample_seeded(7, 4, Sampling::WeightedByQuantity),
+        );
+    }
+
+    #[test]
+    fn it_skips_items_out_of_stock() {
+        let providers = providers();
+        let aggregated_inventory = AggregatedInventory::new(&providers);
+
+        for sampling in [
+            Sampling::Uniform,
+            Sampling::WeightedByQuantity,
+            Sampling::StratifiedByProvider,
+        ] {
+            for seed in 0..10 {
+                let selection = aggregated_inventory.sample_seeded(seed, 100, sampling);
+
+                assert_eq!(selection.len(), 6);
+                assert!(selection.iter().all(|item| item.supply.id != "4f-2"));
+            }
+        }
+    }
+
+    #[test]
+    fn it_gives_every_provider_its_share_when_stratified() {
+        let providers = providers();
+        let aggregated_inventory = AggregatedInventory::new(&providers);
+
+        for seed in 0..10 {
+            let selection =
+                aggregated_inventory.sample_seeded(seed, 5, Sampling::StratifiedByProvider);
+
+            // 4f has a single item in stock, so the others make up for it
+            for (provider, share) in [("4f", 1), ("nike", 2), ("adidas", 2)] {
+                let picked = selection
+                    .iter()
+                    .filter(|item| item.provider == provider)
+                    .count();
+
+                assert_eq!(picked, share);
+            }
+        }
+    }
+}
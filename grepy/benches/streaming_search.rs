@@ -0,0 +1,73 @@
+//! Compares the streaming `Searcher` to `Grepy::find_matches_extended`, which
+//! holds the whole haystack in memory, on logs growing tenfold.
+//!
+//! Run with `cargo bench -p grepy`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use grepy::printer::Printer;
+use grepy::{Grepy, GrepyNeedle, SearchOptions, Searcher};
+use regex::Regex;
+use std::io::Cursor;
+
+const CONTEXT_LINES: usize = 2;
+
+fn log(lines_count: usize) -> String {
+    (0..lines_count)
+        .map(|idx| match idx % 100 {
+            0 => format!("{} ERROR disk is full\n", idx),
+            _ => format!("{} INFO everything is fine\n", idx),
+        })
+        .collect()
+}
+
+fn bench_streaming_search(c: &mut Criterion) {
+    let search_term = Regex::new("ERROR").unwrap();
+    let needle = GrepyNeedle::Regex(&search_term);
+    let options = SearchOptions {
+        before_context: CONTEXT_LINES,
+        after_context: CONTEXT_LINES,
+        ..Default::default()
+    };
+    let mut group = c.benchmark_group("search");
+
+    for lines_count in [1_000, 10_000, 100_000] {
+        let haystack = log(lines_count);
+
+        group.throughput(Throughput::Bytes(haystack.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("find_matches_extended", lines_count),
+            &haystack,
+            |b, haystack| {
+                b.iter(|| {
+                    Grepy::new()
+                        .find_matches_extended(&needle, haystack, CONTEXT_LINES)
+                        .len()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("searcher", lines_count),
+            &haystack,
+            |b, haystack| {
+                b.iter(|| {
+                    let mut output = vec![];
+
+                    Searcher::new(&needle, &options)
+                        .process_lines(
+                            Cursor::new(haystack),
+                            "haystack",
+                            &mut Printer::default().sink(&mut output),
+                        )
+                        .unwrap();
+
+                    output.len()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_streaming_search);
+criterion_main!(benches);
@@ -0,0 +1,148 @@
+use flate2::bufread::MultiGzDecoder;
+use std::io::{BufRead, BufReader, Result};
+use std::path::Path;
+
+/// Compression formats the inputs get decompressed from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    const ALL: [Compression; 3] = [Compression::Gzip, Compression::Zstd, Compression::Bzip2];
+
+    fn magic_bytes(self) -> &'static [u8] {
+        match self {
+            Compression::Gzip => &[0x1f, 0x8b],
+            Compression::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+            Compression::Bzip2 => b"BZh",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+            Compression::Bzip2 => "bz2",
+        }
+    }
+
+    pub fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|compression| bytes.starts_with(compression.magic_bytes()))
+    }
+
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?;
+
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|compression| extension == compression.extension())
+    }
+}
+
+/// Decompresses the input while it is read, if it is compressed.
+///
+/// The files with a compression extension are always looked at, and with
+/// `force` every input is, the standard input too. The magic bytes tell the
+/// actual format, so a `.gz` file which is not compressed is read as it is.
+pub fn decompress<'r, R: BufRead + 'r>(
+    mut reader: R,
+    path: Option<&Path>,
+    force: bool,
+) -> Result<Box<dyn BufRead + 'r>> {
+    if !force && path.and_then(Compression::from_extension).is_none() {
+        return Ok(Box::new(reader));
+    }
+
+    let decompressed: Box<dyn BufRead + 'r> =
+        match Compression::from_magic_bytes(reader.fill_buf()?) {
+            Some(Compression::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+            Some(Compression::Zstd) => {
+                Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+            }
+            Some(Compression::Bzip2) => {
+                Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader)))
+            }
+            None => Box::new(reader),
+        };
+
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Read;
+
+    fn read(path: &str, force: bool) -> Vec<u8> {
+        let file = BufReader::new(File::open(path).unwrap());
+        let mut text = vec![];
+
+        decompress(file, Some(Path::new(path)), force)
+            .unwrap()
+            .read_to_end(&mut text)
+            .unwrap();
+
+        text
+    }
+
+    #[test]
+    fn it_decompresses_the_fixtures() {
+        let quote = std::fs::read("input/quote.md").unwrap();
+
+        for path in [
+            "input/quote.md.gz",
+            "input/quote.md.zst",
+            "input/quote.md.bz2",
+        ] {
+            assert_eq!(read(path, false), quote, "{}", path);
+        }
+
+        assert_eq!(read("input/quote.md", true), quote);
+    }
+
+    #[test]
+    fn it_tells_the_format_by_magic_bytes_only_when_forced() {
+        let compressed = std::fs::read("input/quote.md.gz").unwrap();
+        let mut text = vec![];
+
+        decompress(compressed.as_slice(), None, false)
+            .unwrap()
+            .read_to_end(&mut text)
+            .unwrap();
+
+        assert_eq!(text, compressed);
+
+        text.clear();
+        decompress(compressed.as_slice(), None, true)
+            .unwrap()
+            .read_to_end(&mut text)
+            .unwrap();
+
+        assert_eq!(text, std::fs::read("input/quote.md").unwrap());
+    }
+
+    #[test]
+    fn it_reads_mislabeled_files_as_they_are() {
+        let text = b"not compressed at all\n";
+        let mut read = vec![];
+
+        decompress(&text[..], Some(Path::new("notes.gz")), false)
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+
+        assert_eq!(read, text);
+        assert_eq!(
+            Compression::from_extension(Path::new("app.log.1.zst")),
+            Some(Compression::Zstd)
+        );
+    }
+}
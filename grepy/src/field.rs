@@ -0,0 +1,357 @@
+use anyhow::{bail, Result};
+use serde_json::value::RawValue;
+use std::char::REPLACEMENT_CHARACTER;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Field of the records the patterns are matched against, rather than the whole lines
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldSelector {
+    /// Reference tokens of a JSON pointer into every line, taken as a JSON document
+    JsonPointer(Vec<String>),
+    /// Column of every line but the first one, taken as a CSV record, named by the header on the first line
+    CsvColumn(String),
+}
+
+impl FieldSelector {
+    /// Parses a JSON pointer such as `/data/secret`, where `~1` stands for a `/` and `~0` for a `~`
+    pub fn json_pointer(pointer: &str) -> Result<Self> {
+        if pointer.is_empty() {
+            return Ok(FieldSelector::JsonPointer(vec![]));
+        }
+
+        if !pointer.starts_with('/') {
+            bail!("invalid JSON pointer `{}`, it must start with `/`", pointer);
+        }
+
+        let tokens = pointer[1..]
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect();
+
+        Ok(FieldSelector::JsonPointer(tokens))
+    }
+}
+
+/// Finds the value the pointer points to in the JSON record, and tells its byte range.
+///
+/// The range of a string is the one within its quotes, with the escapes left
+/// as they are, see `Unescaped` for the value the patterns are matched against.
+pub fn find_json_field(record: &[u8], pointer: &[String]) -> Option<Range<usize>> {
+    let record = std::str::from_utf8(record).ok()?;
+    let mut value: &RawValue = serde_json::from_str(record).ok()?;
+
+    for token in pointer {
+        value = match value.get().trim_start().as_bytes().first()? {
+            b'{' => serde_json::from_str::<HashMap<String, &RawValue>>(value.get())
+                .ok()?
+                .remove(token)?,
+            b'[' => *serde_json::from_str::<Vec<&RawValue>>(value.get())
+                .ok()?
+                .get(token.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    // the raw values are slices of the record, so their offsets are where they are in it
+    let start = value.get().as_ptr() as usize - record.as_ptr() as usize;
+    let end = start + value.get().len();
+
+    match value.get().starts_with('"') {
+        true => Some(start + 1..end - 1),
+        false => Some(start..end),
+    }
+}
+
+/// Splits the CSV record into the byte ranges of its fields, within the quotes of the quoted ones.
+///
+/// The quotes doubled in a quoted field are left as they are, see `Unescaped`
+/// for the value the patterns are matched against. The records are
+/// searched line by line, so a quoted field cannot span many lines.
+pub fn csv_fields(record: &[u8]) -> Vec<Range<usize>> {
+    let mut fields = vec![];
+    let mut start = 0;
+
+    loop {
+        let (field, after) = match record.get(start) {
+            Some(b'"') => {
+                let mut end = start + 1;
+
+                while end < record.len() {
+                    match (record[end], record.get(end + 1)) {
+                        (b'"', Some(b'"')) => end += 2,
+                        (b'"', _) => break,
+                        _ => end += 1,
+                    }
+                }
+
+                (
+                    start + 1..end.min(record.len()),
+                    (end + 1).min(record.len()),
+                )
+            }
+            _ => {
+                let end = start + comma_or_end(&record[start..]);
+
+                (start..end, end)
+            }
+        };
+
+        fields.push(field);
+
+        // anything between the closing quote and the comma is left out
+        let comma = after + comma_or_end(&record[after..]);
+
+        if comma == record.len() {
+            return fields;
+        }
+
+        start = comma + 1;
+    }
+}
+
+/// Tells the column the header of a CSV input names, if any
+pub fn csv_column(header: &[u8], name: &str) -> Option<usize> {
+    csv_fields(header)
+        .into_iter()
+        .position(|field| String::from_utf8_lossy(&header[field]).replace("\"\"", "\"") == name)
+}
+
+/// Value of a field with its escapes decoded, the way the patterns are matched against it.
+///
+/// The escapes of a JSON string, and the doubled quotes of a quoted CSV field,
+/// get decoded, while every byte decoded remembers where it comes from in the
+/// record, so the matches get highlighted where they are in the line.
+#[derive(Debug, Default)]
+pub struct Unescaped {
+    pub text: Vec<u8>,
+    /// Where every byte of the text starts in the record, then where the field ends
+    starts: Vec<usize>,
+}
+
+impl Unescaped {
+    /// Decodes the field of the record found by the selector, and tells if it had anything to decode.
+    ///
+    /// Only the fields within quotes, strings or quoted fields, have escapes.
+    pub fn decode(&mut self, selector: &FieldSelector, record: &[u8], field: Range<usize>) -> bool {
+        let raw = &record[field.clone()];
+        let quoted = field.start > 0 && record[field.start - 1] == b'"';
+
+        self.text.clear();
+        self.starts.clear();
+
+        let escaped = match selector {
+            FieldSelector::JsonPointer(_) => raw.contains(&b'\\'),
+            FieldSelector::CsvColumn(_) => raw.windows(2).any(|pair| pair == b"\"\""),
+        };
+
+        if !quoted || !escaped {
+            return false;
+        }
+
+        let mut idx = 0;
+
+        while idx < raw.len() {
+            let (decoded, len) = match selector {
+                FieldSelector::JsonPointer(_) => json_escape(&raw[idx..]),
+                FieldSelector::CsvColumn(_) if raw[idx..].starts_with(b"\"\"") => {
+                    (Decoded::Byte(b'"'), 2)
+                }
+                FieldSelector::CsvColumn(_) => (Decoded::Byte(raw[idx]), 1),
+            };
+            let start = field.start + idx;
+
+            match decoded {
+                Decoded::Byte(byte) => {
+                    self.text.push(byte);
+                    self.starts.push(start);
+                }
+                Decoded::Char(char) => {
+                    let mut utf8 = [0; 4];
+
+                    for &byte in char.encode_utf8(&mut utf8).as_bytes() {
+                        self.text.push(byte);
+                        self.starts.push(start);
+                    }
+                }
+            }
+
+            idx += len;
+        }
+
+        self.starts.push(field.end);
+
+        true
+    }
+
+    /// The range of the record the span of the decoded text comes from
+    pub fn raw_span(&self, span: &Range<usize>) -> Range<usize> {
+        self.starts[span.start]..self.starts[span.end]
+    }
+}
+
+/// What an escape, or a byte as it is, stands for
+enum Decoded {
+    Byte(u8),
+    Char(char),
+}
+
+/// Decodes the escape the JSON string starts with, if any, and tells how many bytes it takes
+fn json_escape(text: &[u8]) -> (Decoded, usize) {
+    let byte = match text {
+        [b'\\', b'u', ..] => return unicode_escape(text),
+        [b'\\', escaped, ..] => match escaped {
+            b'b' => b'\x08',
+            b'f' => b'\x0c',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            // `\"`, `\\` and `\/` stand for themselves
+            escaped => *escaped,
+        },
+        _ => return (Decoded::Byte(text[0]), 1),
+    };
+
+    (Decoded::Byte(byte), 2)
+}
+
+/// Decodes a `\uXXXX` escape, or the two of a surrogate pair, the lone surrogates standing for U+FFFD
+fn unicode_escape(text: &[u8]) -> (Decoded, usize) {
+    let code_unit = |text: &[u8]| match text.starts_with(b"\\u") {
+        true => u32::from_str_radix(std::str::from_utf8(text.get(2..6)?).ok()?, 16).ok(),
+        false => None,
+    };
+    let decoded =
+        |code_point| Decoded::Char(char::from_u32(code_point).unwrap_or(REPLACEMENT_CHARACTER));
+
+    match code_unit(text) {
+        None => (Decoded::Byte(text[0]), 1),
+        Some(high @ 0xD800..=0xDBFF) => match code_unit(&text[6..]) {
+            Some(low @ 0xDC00..=0xDFFF) => (
+                decoded(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)),
+                12,
+            ),
+            _ => (decoded(REPLACEMENT_CHARACTER.into()), 6),
+        },
+        Some(code_point) => (decoded(code_point), 6),
+    }
+}
+
+fn comma_or_end(text: &[u8]) -> usize {
+    text.iter()
+        .position(|&byte| byte == b',')
+        .unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_field<'r>(record: &'r str, pointer: &str) -> Option<&'r str> {
+        let tokens = match FieldSelector::json_pointer(pointer).unwrap() {
+            FieldSelector::JsonPointer(tokens) => tokens,
+            FieldSelector::CsvColumn(_) => unreachable!(),
+        };
+
+        find_json_field(record.as_bytes(), &tokens).map(|field| &record[field])
+    }
+
+    fn csv_field_texts(record: &str) -> Vec<&str> {
+        csv_fields(record.as_bytes())
+            .into_iter()
+            .map(|field| &record[field])
+            .collect()
+    }
+
+    #[test]
+    fn it_parses_json_pointers() {
+        assert_eq!(
+            FieldSelector::json_pointer("/data/a~1b/m~0n/0").unwrap(),
+            FieldSelector::JsonPointer(vec!["data".into(), "a/b".into(), "m~n".into(), "0".into()])
+        );
+        assert_eq!(
+            FieldSelector::json_pointer("").unwrap(),
+            FieldSelector::JsonPointer(vec![])
+        );
+        assert!(FieldSelector::json_pointer("data/secret").is_err());
+    }
+
+    #[test]
+    fn it_finds_the_fields_json_pointers_point_to() {
+        let record = r#"{"id": 1, "data": {"secret": "hun\"ter2", "keys": [12, {"a/b": null}]}}"#;
+
+        assert_eq!(json_field(record, "/data/secret"), Some(r#"hun\"ter2"#));
+        assert_eq!(json_field(record, "/id"), Some("1"));
+        assert_eq!(json_field(record, "/data/keys/0"), Some("12"));
+        assert_eq!(json_field(record, "/data/keys/1/a~1b"), Some("null"));
+        assert_eq!(
+            json_field(record, "/data/keys"),
+            Some(r#"[12, {"a/b": null}]"#)
+        );
+        assert_eq!(json_field(record, ""), Some(record));
+        assert_eq!(json_field(record, "/data/keys/2"), None);
+        assert_eq!(json_field(record, "/id/0"), None);
+        assert_eq!(json_field(record, "/missing"), None);
+        assert_eq!(json_field("not json", "/id"), None);
+    }
+
+    #[test]
+    fn it_decodes_the_escapes_of_the_fields_within_quotes() {
+        let decoded = |selector: &FieldSelector, record: &str, field: Range<usize>| {
+            let mut unescaped = Unescaped::default();
+
+            match unescaped.decode(selector, record.as_bytes(), field) {
+                true => Some(String::from_utf8(unescaped.text).unwrap()),
+                false => None,
+            }
+        };
+        let json = FieldSelector::JsonPointer(vec!["m".into()]);
+        let csv = FieldSelector::CsvColumn("m".into());
+        let record = r#"{"m":"say \"hi\" \u00e9\ud83d\ude00\ud800\\\/\n"}"#;
+
+        assert_eq!(
+            decoded(
+                &json,
+                record,
+                find_json_field(record.as_bytes(), &["m".into()]).unwrap()
+            ),
+            Some("say \"hi\" é😀\u{fffd}\\/\n".into())
+        );
+        assert_eq!(decoded(&json, r#"{"m":"hi"}"#, 6..8), None);
+        assert_eq!(decoded(&json, r#"{"m":12}"#, 5..7), None);
+        assert_eq!(
+            decoded(&csv, r#"1,"say ""hi""""#, 3..13),
+            Some(r#"say "hi""#.into())
+        );
+        assert_eq!(decoded(&csv, "1,2", 2..3), None);
+    }
+
+    #[test]
+    fn it_maps_the_spans_of_the_decoded_text_to_the_record() {
+        let record = br#"{"m":"a\"b\u00e9c"}"#;
+        let mut unescaped = Unescaped::default();
+
+        assert!(unescaped.decode(&FieldSelector::JsonPointer(vec!["m".into()]), record, 6..17));
+        assert_eq!(unescaped.text, "a\"béc".as_bytes());
+        assert_eq!(&record[unescaped.raw_span(&(1..2))], br#"\""#);
+        assert_eq!(&record[unescaped.raw_span(&(0..5))], br#"a\"b\u00e9"#);
+        assert_eq!(&record[unescaped.raw_span(&(3..6))], br#"\u00e9c"#);
+    }
+
+    #[test]
+    fn it_splits_csv_records_into_fields() {
+        assert_eq!(
+            csv_field_texts(r#"1,"Doe, John","say ""hi""",,last"#),
+            ["1", "Doe, John", r#"say ""hi"""#, "", "last"]
+        );
+        assert_eq!(csv_field_texts(""), [""]);
+        assert_eq!(csv_field_texts("a,"), ["a", ""]);
+        assert_eq!(csv_field_texts(r#""unclosed, quote"#), ["unclosed, quote"]);
+        assert_eq!(
+            csv_column(br#"id,"the ""name""",secret"#, r#"the "name""#),
+            Some(1)
+        );
+        assert_eq!(csv_column(b"id,name,secret", "secret"), Some(2));
+        assert_eq!(csv_column(b"id,name", "secret"), None);
+    }
+}
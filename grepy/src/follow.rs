@@ -0,0 +1,200 @@
+use std::fs::{self, File, Metadata};
+use std::io::{ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Reads a file as it grows, the way `tail -f` does, from its start.
+///
+/// Once the end of the file is reached, the reads wait for more to be
+/// appended rather than telling the end. A file truncated, or replaced by
+/// another one at the same path, as the logs are when rotated, gets read
+/// again from its start. The reading ends only once stopped, with what was
+/// appended until then.
+pub struct Follower {
+    path: PathBuf,
+    file: File,
+    id: Option<FileId>,
+    /// Bytes read from the current file
+    position: u64,
+    poll_interval: Duration,
+    stopped: Arc<AtomicBool>,
+}
+
+/// Device and inode, telling the files apart where the platform has them
+type FileId = (u64, u64);
+
+impl Follower {
+    /// Opens the file, to look at it again every `poll_interval` once its end has been reached
+    pub fn open(path: &Path, poll_interval: Duration) -> Result<Self> {
+        let file = File::open(path)?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            id: file_id(&file.metadata()?),
+            file,
+            position: 0,
+            poll_interval,
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Flag which, once set, ends the reading at the end of the file rather than waiting for more
+    pub fn stopper(&self) -> Arc<AtomicBool> {
+        self.stopped.clone()
+    }
+
+    /// Opens the file at the path again, if it got truncated or replaced, and tells if it did
+    fn reopen_if_rotated(&mut self) -> Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // between the rotated file moved away and the new one created
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(error),
+        };
+
+        if file_id(&metadata) == self.id && metadata.len() >= self.position {
+            return Ok(false);
+        }
+
+        self.file = File::open(&self.path)?;
+        self.id = file_id(&self.file.metadata()?);
+        self.position = 0;
+
+        Ok(true)
+    }
+}
+
+impl Read for Follower {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let read = self.file.read(buf)?;
+
+            if read > 0 || buf.is_empty() {
+                self.position += read as u64;
+
+                return Ok(read);
+            }
+
+            if self.reopen_if_rotated()? {
+                continue;
+            }
+
+            if self.stopped.load(Ordering::Relaxed) {
+                return Ok(0);
+            }
+
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// The files replaced by bigger ones go unnoticed, as only the truncated ones can be told
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::Line;
+    use crate::search::{SearchOptions, Searcher, Sink};
+    use crate::GrepyNeedle;
+    use anyhow::Result;
+    use std::fs::OpenOptions;
+    use std::io::{BufReader, Write};
+    use std::ops::Range;
+    use std::sync::mpsc::{self, Sender};
+
+    /// Sends the lines the grep way, as they are found
+    struct ChannelSink(Sender<String>);
+
+    impl Sink for ChannelSink {
+        fn matched(
+            &mut self,
+            _name: &str,
+            line: &Line,
+            _spans: &[Range<usize>],
+            _replacements: &[Vec<u8>],
+        ) -> Result<()> {
+            let text = String::from_utf8_lossy(line.text);
+            self.0.send(format!("{}:{}", line.idx + 1, text))?;
+
+            Ok(())
+        }
+
+        fn context(&mut self, _name: &str, line: &Line) -> Result<()> {
+            let text = String::from_utf8_lossy(line.text);
+            self.0.send(format!("{}-{}", line.idx + 1, text))?;
+
+            Ok(())
+        }
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn it_keeps_matching_the_lines_appended_across_rotations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+
+        fs::write(&path, "boot\nerror: disk full\n").unwrap();
+
+        let follower = Follower::open(&path, Duration::from_millis(5)).unwrap();
+        let stopper = follower.stopper();
+        let (sender, receiver) = mpsc::channel();
+        let searching = thread::spawn(move || {
+            let needle = GrepyNeedle::PlainText("error");
+            let options = SearchOptions {
+                after_context: 1,
+                ..Default::default()
+            };
+
+            Searcher::new(&needle, &options)
+                .process_lines(
+                    BufReader::new(follower),
+                    "app.log",
+                    &mut ChannelSink(sender),
+                )
+                .unwrap()
+        });
+        let next = || receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(next(), "2:error: disk full");
+
+        append(&path, "retrying\nok\n");
+        assert_eq!(next(), "3-retrying");
+
+        // truncated in place, the way `copytruncate` rotates
+        fs::write(&path, "error: timeout\n").unwrap();
+        assert_eq!(next(), "5:error: timeout");
+
+        // replaced by a new file, the way the rotated file gets moved away
+        let new_path = dir.path().join("app.log.new");
+        fs::write(&new_path, "error: again\n").unwrap();
+        fs::rename(&new_path, &path).unwrap();
+        assert_eq!(next(), "6:error: again");
+
+        append(&path, "done\nerror: last, and not waited for\n");
+        stopper.store(true, Ordering::Relaxed);
+
+        assert!(searching.join().unwrap());
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            ["7-done", "8:error: last, and not waited for"]
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use crate::printer::Line;
+use crate::search::{ReplacedLine, Sink, Stats};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Pattern matched approximately, within a number of edits.
+///
+/// The edits are the ones of the Damerau-Levenshtein distance: inserting,
+/// deleting or substituting a character, and transposing two adjacent ones.
+/// Each of them costs 1, and a transposition is counted only when the
+/// characters are not edited any further.
+#[derive(Clone, Debug)]
+pub struct Fuzzy {
+    chars: Vec<char>,
+    bytes: Vec<u8>,
+    max_distance: usize,
+    ignore_case: bool,
+}
+
+/// Part of a text matching a fuzzy pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuzzyMatch {
+    /// Byte offsets within the text
+    pub span: Range<usize>,
+    /// Edits turning the pattern into the matched part
+    pub distance: usize,
+}
+
+impl Fuzzy {
+    pub fn new(pattern: &str, max_distance: usize, ignore_case: bool) -> Self {
+        let pattern = match ignore_case {
+            true => pattern.chars().map(lowercase).collect(),
+            false => pattern.to_owned(),
+        };
+
+        Self {
+            chars: pattern.chars().collect(),
+            bytes: pattern.into_bytes(),
+            max_distance,
+            ignore_case,
+        }
+    }
+
+    pub fn max_distance(&self) -> usize {
+        self.max_distance
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        !self.find_matches(text).is_empty()
+    }
+
+    /// Finds the parts of the text within the distance of the pattern, in the order of the text.
+    ///
+    /// The parts do not overlap, and the closest ones win over the ones they would overlap.
+    pub fn find_matches(&self, text: &str) -> Vec<FuzzyMatch> {
+        let (offsets, chars): (Vec<usize>, Vec<char>) = text
+            .char_indices()
+            .map(|(offset, char)| (offset, self.fold_case(char)))
+            .unzip();
+
+        let offset = |idx: usize| offsets.get(idx).copied().unwrap_or(text.len());
+
+        approximate_matches(&self.chars, &chars, self.max_distance)
+            .into_iter()
+            .map(|matched| FuzzyMatch {
+                span: offset(matched.span.start)..offset(matched.span.end),
+                distance: matched.distance,
+            })
+            .collect()
+    }
+
+    /// Same as `find_matches`, but byte by byte, for the texts which are not valid UTF-8
+    pub fn find_matches_in_bytes(&self, text: &[u8]) -> Vec<FuzzyMatch> {
+        let bytes: Vec<u8> = match self.ignore_case {
+            true => text.to_ascii_lowercase(),
+            false => text.to_vec(),
+        };
+
+        approximate_matches(&self.bytes, &bytes, self.max_distance)
+    }
+
+    /// Edits turning the pattern into the whole text
+    pub fn distance(&self, text: &str) -> usize {
+        let chars: Vec<char> = text.chars().map(|char| self.fold_case(char)).collect();
+
+        distance(&self.chars, &chars)
+    }
+
+    fn fold_case(&self, char: char) -> char {
+        match self.ignore_case {
+            true => lowercase(char),
+            false => char,
+        }
+    }
+}
+
+fn lowercase(char: char) -> char {
+    char.to_lowercase().next().unwrap_or(char)
+}
+
+/// Unit of the texts the patterns are matched against, a character or a byte
+trait Unit: PartialEq {
+    fn is_word(&self) -> bool;
+}
+
+impl Unit for char {
+    fn is_word(&self) -> bool {
+        self.is_alphanumeric() || *self == '_'
+    }
+}
+
+impl Unit for u8 {
+    fn is_word(&self) -> bool {
+        self.is_ascii_alphanumeric() || *self == b'_'
+    }
+}
+
+/// Cell of the edit distance table: the distance, and where the alignment starts in the text
+type Cell = (usize, usize);
+
+/// Picks the cell with the smaller distance, or with the longer alignment on ties,
+/// so a substituted first character is not taken for a deleted one
+fn closer(a: Cell, b: Cell) -> Cell {
+    match a.0 < b.0 || (a.0 == b.0 && a.1 <= b.1) {
+        true => a,
+        false => b,
+    }
+}
+
+/// Finds the parts of the text within `max_distance` edits of the pattern, with indexes of the units.
+///
+/// This is the edit distance table of Sellers' algorithm, where aligning the
+/// pattern may start anywhere in the text for free, built a column at a time.
+fn approximate_matches<T: Unit>(pattern: &[T], text: &[T], max_distance: usize) -> Vec<FuzzyMatch> {
+    if pattern.is_empty() {
+        return vec![];
+    }
+
+    let mut before_previous: Vec<Cell> = vec![];
+    let mut previous: Vec<Cell> = (0..=pattern.len()).map(|idx| (idx, 0)).collect();
+    let mut column: Vec<Cell> = Vec::with_capacity(pattern.len() + 1);
+    let mut candidates = vec![];
+
+    for text_idx in 1..=text.len() {
+        column.clear();
+        column.push((0, text_idx));
+
+        for pattern_idx in 1..=pattern.len() {
+            let substitution = (pattern[pattern_idx - 1] != text[text_idx - 1]) as usize;
+            let mut cell = (
+                previous[pattern_idx - 1].0 + substitution,
+                previous[pattern_idx - 1].1,
+            );
+
+            cell = closer(cell, (previous[pattern_idx].0 + 1, previous[pattern_idx].1));
+            cell = closer(
+                cell,
+                (column[pattern_idx - 1].0 + 1, column[pattern_idx - 1].1),
+            );
+
+            if pattern_idx > 1
+                && text_idx > 1
+                && pattern[pattern_idx - 1] == text[text_idx - 2]
+                && pattern[pattern_idx - 2] == text[text_idx - 1]
+            {
+                let transposed = before_previous[pattern_idx - 2];
+                cell = closer(cell, (transposed.0 + 1, transposed.1));
+            }
+
+            column.push(cell);
+        }
+
+        let (distance, start) = column[pattern.len()];
+
+        if distance <= max_distance && start < text_idx {
+            candidates.push(FuzzyMatch {
+                span: start..text_idx,
+                distance,
+            });
+        }
+
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut column);
+    }
+
+    // the closest matches first, then the ones not overlapping them; on ties, the ones
+    // not starting or ending with a space or a punctuation, then the ones as long as the pattern
+    candidates.sort_by_key(|matched| {
+        let span = &matched.span;
+        let edges_not_words = [&text[span.start], &text[span.end - 1]]
+            .iter()
+            .filter(|unit| !unit.is_word())
+            .count();
+
+        (
+            matched.distance,
+            edges_not_words,
+            span.len().abs_diff(pattern.len()),
+            span.start,
+        )
+    });
+
+    let mut accepted: BTreeMap<usize, FuzzyMatch> = BTreeMap::new();
+
+    for candidate in candidates {
+        let span = &candidate.span;
+        let overlaps_before = accepted
+            .range(..=span.start)
+            .next_back()
+            .is_some_and(|(_, matched)| matched.span.end > span.start);
+        let overlaps_after = accepted
+            .range(span.start..)
+            .next()
+            .is_some_and(|(&start, _)| start < span.end);
+
+        if !overlaps_before && !overlaps_after {
+            accepted.insert(span.start, candidate);
+        }
+    }
+
+    accepted.into_values().collect()
+}
+
+/// Edits turning the pattern into the whole text, the optimal string alignment way
+fn distance<T: PartialEq>(pattern: &[T], text: &[T]) -> usize {
+    let width = text.len() + 1;
+    let mut table: Vec<usize> = vec![0; (pattern.len() + 1) * width];
+
+    for pattern_idx in 0..=pattern.len() {
+        for text_idx in 0..=text.len() {
+            let distance = match (pattern_idx, text_idx) {
+                (0, _) => text_idx,
+                (_, 0) => pattern_idx,
+                _ => {
+                    let substitution = (pattern[pattern_idx - 1] != text[text_idx - 1]) as usize;
+                    let mut distance = (table[(pattern_idx - 1) * width + text_idx - 1]
+                        + substitution)
+                        .min(table[(pattern_idx - 1) * width + text_idx] + 1)
+                        .min(table[pattern_idx * width + text_idx - 1] + 1);
+
+                    if pattern_idx > 1
+                        && text_idx > 1
+                        && pattern[pattern_idx - 1] == text[text_idx - 2]
+                        && pattern[pattern_idx - 2] == text[text_idx - 1]
+                    {
+                        distance =
+                            distance.min(table[(pattern_idx - 2) * width + text_idx - 2] + 1);
+                    }
+
+                    distance
+                }
+            };
+
+            table[pattern_idx * width + text_idx] = distance;
+        }
+    }
+
+    table[pattern.len() * width + text.len()]
+}
+
+/// Matching line held back until the whole input has been searched
+struct RankedLine {
+    distance: usize,
+    idx: usize,
+    offset: u64,
+    text: Vec<u8>,
+    field: Option<Range<usize>>,
+    spans: Vec<Range<usize>>,
+    replacements: Vec<Vec<u8>>,
+}
+
+/// Passes the matching lines of every input on to the inner sink ranked by
+/// their distance to the fuzzy pattern, the closest first, once the whole
+/// input has been searched. The lines at the same distance keep their order.
+pub struct RankedSink<S> {
+    inner: S,
+    lines: Vec<RankedLine>,
+}
+
+impl<S: Sink> RankedSink<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            lines: vec![],
+        }
+    }
+}
+
+impl<S: Sink> Sink for RankedSink<S> {
+    fn needs_spans(&self) -> bool {
+        true
+    }
+
+    fn stops_at_first_match(&self) -> bool {
+        self.inner.stops_at_first_match()
+    }
+
+    /// The context of lines printed out of order would mean nothing
+    fn wants_context(&self) -> bool {
+        false
+    }
+
+    fn reports_binary(&self) -> bool {
+        self.inner.reports_binary()
+    }
+
+    fn begin(&mut self, name: &str) -> Result<()> {
+        self.inner.begin(name)
+    }
+
+    fn matched(
+        &mut self,
+        _name: &str,
+        line: &Line,
+        spans: &[Range<usize>],
+        replacements: &[Vec<u8>],
+    ) -> Result<()> {
+        self.lines.push(RankedLine {
+            distance: line.distance.unwrap_or(0),
+            idx: line.idx,
+            offset: line.offset,
+            text: line.text.to_vec(),
+            field: line.field.clone(),
+            spans: spans.to_vec(),
+            replacements: replacements.to_vec(),
+        });
+
+        Ok(())
+    }
+
+    fn binary_matches(&mut self, name: &str) -> Result<()> {
+        self.inner.binary_matches(name)
+    }
+
+    fn replaced(&mut self, name: &str, lines: &[ReplacedLine]) -> Result<()> {
+        self.inner.replaced(name, lines)
+    }
+
+    fn end(&mut self, name: &str, stats: &Stats) -> Result<()> {
+        self.lines.sort_by_key(|line| line.distance);
+
+        for ranked in self.lines.drain(..) {
+            let line = Line {
+                idx: ranked.idx,
+                offset: ranked.offset,
+                text: &ranked.text,
+                field: ranked.field,
+                distance: Some(ranked.distance),
+            };
+
+            self.inner
+                .matched(name, &line, &ranked.spans, &ranked.replacements)?;
+        }
+
+        self.inner.end(name, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::FieldSelector;
+    use crate::printer::Printer;
+    use crate::{GrepyNeedle, SearchOptions, Searcher};
+
+    fn matched<'t>(fuzzy: &Fuzzy, text: &'t str) -> Vec<(&'t str, usize)> {
+        fuzzy
+            .find_matches(text)
+            .into_iter()
+            .map(|matched| (&text[matched.span], matched.distance))
+            .collect()
+    }
+
+    #[test]
+    fn it_matches_within_the_edit_distance() {
+        let fuzzy = Fuzzy::new("Colorado", 1, false);
+
+        assert_eq!(matched(&fuzzy, "in Colorado"), vec![("Colorado", 0)]);
+        // substitution, deletion, insertion and transposition
+        assert_eq!(matched(&fuzzy, "in Kolorado"), vec![("Kolorado", 1)]);
+        assert_eq!(matched(&fuzzy, "in Colrado"), vec![("Colrado", 1)]);
+        assert_eq!(matched(&fuzzy, "in Colorrado"), vec![("Colorrado", 1)]);
+        assert_eq!(matched(&fuzzy, "in Coloardo"), vec![("Coloardo", 1)]);
+        assert!(matched(&fuzzy, "in Kolorrado").is_empty());
+        assert!(matched(&Fuzzy::new("colorado", 1, false), "in COLORADO").is_empty());
+        assert_eq!(
+            matched(&Fuzzy::new("colorado", 1, true), "in COLORADo"),
+            vec![("COLORADo", 0)]
+        );
+    }
+
+    #[test]
+    fn it_finds_every_match_without_overlaps() {
+        let fuzzy = Fuzzy::new("ab", 1, false);
+
+        assert_eq!(matched(&fuzzy, "abab"), vec![("ab", 0), ("ab", 0)]);
+        assert_eq!(
+            matched(&Fuzzy::new("żółw", 1, false), "żółw i żołw"),
+            vec![("żółw", 0), ("żołw", 1)]
+        );
+        assert_eq!(
+            Fuzzy::new("caf", 1, false).find_matches_in_bytes(b"\xe9 caf\xe9"),
+            vec![FuzzyMatch {
+                span: 2..5,
+                distance: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn it_tells_the_distance_of_whole_texts() {
+        let fuzzy = Fuzzy::new("identifier", 2, false);
+
+        assert_eq!(fuzzy.distance("identifier"), 0);
+        assert_eq!(fuzzy.distance("identifeir"), 1);
+        assert_eq!(fuzzy.distance("idnetifer"), 2);
+        assert_eq!(fuzzy.distance(""), 10);
+    }
+
+    #[test]
+    fn it_ranks_the_lines_by_distance() {
+        let fuzzy = Fuzzy::new("identifier", 2, false);
+        let needle = GrepyNeedle::Fuzzy(&fuzzy);
+        let options = SearchOptions::default();
+        let mut output = vec![];
+
+        Searcher::new(&needle, &options)
+            .process_lines(
+                "an idnetifer\nno match\nthe identifier\nidentifeir\n".as_bytes(),
+                "log",
+                &mut RankedSink::new(Printer::default().sink(&mut output)),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "3:the identifier\n4:identifeir\n1:an idnetifer\n"
+        );
+    }
+
+    #[test]
+    fn it_ranks_the_lines_by_the_distance_they_matched_at() {
+        let fuzzy = Fuzzy::new("café", 2, false);
+        let needle = GrepyNeedle::Fuzzy(&fuzzy);
+        let options = SearchOptions::default();
+        let mut output = vec![];
+
+        // not UTF-8, so the pattern is matched byte by byte, and `é` takes two of them
+        Searcher::new(&needle, &options)
+            .process_lines(
+                &b"\xff cafe\n\xff caf\xc3\xa9\n\xff caf\xc3\n"[..],
+                "log",
+                &mut RankedSink::new(Printer::default().sink(&mut output)),
+            )
+            .unwrap();
+
+        assert_eq!(output, b"2:\xff caf\xc3\xa9\n3:\xff caf\xc3\n1:\xff cafe\n");
+
+        let fuzzy = Fuzzy::new("identifier", 2, false);
+        let needle = GrepyNeedle::Fuzzy(&fuzzy);
+        let options = SearchOptions {
+            field: Some(FieldSelector::json_pointer("/msg").unwrap()),
+            ..Default::default()
+        };
+        let mut output = vec![];
+
+        // the field matches as it is decoded, rather than as it is escaped in the line
+        Searcher::new(&needle, &options)
+            .process_lines(
+                r#"{"msg":"identifeir"}
+{"msg":"ident\u0069fier"}
+"#
+                .as_bytes(),
+                "log",
+                &mut RankedSink::new(Printer::default().sink(&mut output)),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2:{\"msg\":\"ident\\u0069fier\"}\tident\\u0069fier\n1:{\"msg\":\"identifeir\"}\tidentifeir\n"
+        );
+    }
+}
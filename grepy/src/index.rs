@@ -0,0 +1,419 @@
+use crate::decompress::decompress;
+use crate::query::{Node, Query};
+use crate::search::decode;
+use crate::walk::{self, Input, WalkOptions};
+use crate::GrepyNeedle;
+use anyhow::{Context, Result};
+use regex_syntax::hir::{Class, Hir, HirKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, Metadata};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+
+/// Name of the index file, at the root of the directory it indexes
+pub const INDEX_FILE_NAME: &str = ".grepy-index";
+
+/// Trigram index of the files of a directory, telling which of them can't match without reading them.
+///
+/// The trigrams are the ones of the text as it gets searched, decompressed
+/// and decoded, with the ASCII letters lower-cased so the patterns ignoring
+/// case narrow the files too. The files changed since the index was built,
+/// as told by their size and modification time, may match anything, and so
+/// may the files which are not indexed at all.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Index {
+    /// Files indexed, by their paths relative to the directory
+    files: BTreeMap<PathBuf, IndexedFile>,
+    /// Ids of the files every trigram is found in, in order
+    trigrams: BTreeMap<u32, Vec<u32>>,
+}
+
+/// What a file was like when indexed
+#[derive(Debug, Deserialize, Serialize)]
+struct IndexedFile {
+    id: u32,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl Index {
+    /// Indexes the files a search of the directory goes through, skipping the ones which can't be read
+    pub fn build(dir: &str, options: &WalkOptions) -> Result<Self> {
+        let mut index = Index::default();
+        let mut text = vec![];
+
+        for input in walk::inputs(&[dir], options) {
+            let path = match input? {
+                Input::File(path) => path,
+                Input::Stdin => continue,
+            };
+            let relative_path = path.strip_prefix(dir).unwrap_or(&path).to_owned();
+
+            // the index of an earlier build is nothing to search
+            if relative_path == Path::new(INDEX_FILE_NAME) {
+                continue;
+            }
+
+            text.clear();
+
+            let read = fs::metadata(&path).and_then(|metadata| {
+                let file = BufReader::new(File::open(&path)?);
+                decode(decompress(file, Some(&path), false)?).read_to_end(&mut text)?;
+
+                Ok(metadata)
+            });
+            let metadata = match read {
+                Ok(metadata) => metadata,
+                // searched without the index, the error tells what is wrong with it
+                Err(_) => continue,
+            };
+            let id = index.files.len() as u32;
+
+            for trigram in trigrams(&text) {
+                index.trigrams.entry(trigram).or_default().push(id);
+            }
+
+            index.files.insert(
+                relative_path,
+                IndexedFile {
+                    id,
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                },
+            );
+        }
+
+        Ok(index)
+    }
+
+    /// Reads the index of the directory, if it has one
+    pub fn read(dir: &str) -> Result<Option<Self>> {
+        let path = Path::new(dir).join(INDEX_FILE_NAME);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error).with_context(|| path.display().to_string()),
+        };
+
+        serde_json::from_reader(BufReader::new(file))
+            .map(Some)
+            .with_context(|| path.display().to_string())
+    }
+
+    /// Writes the index at the root of the directory, replacing the one there at once
+    pub fn write(&self, dir: &str) -> Result<()> {
+        let mut index_file = NamedTempFile::new_in(dir)?;
+        let mut writer = BufWriter::new(&mut index_file);
+
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        index_file.persist(Path::new(dir).join(INDEX_FILE_NAME))?;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Tells if the file, by its path relative to the directory, may have the trigrams,
+    /// which it may unless it is indexed and has not changed since
+    pub fn may_match(
+        &self,
+        relative_path: &Path,
+        metadata: &Metadata,
+        required: &Trigrams,
+    ) -> bool {
+        let file = match self.files.get(relative_path) {
+            Some(file) => file,
+            None => return true,
+        };
+
+        if file.size != metadata.len() || metadata.modified().ok() != Some(file.modified) {
+            return true;
+        }
+
+        required.is_found(&|trigram| {
+            self.trigrams
+                .get(&trigram)
+                .is_some_and(|ids| ids.binary_search(&file.id).is_ok())
+        })
+    }
+}
+
+/// Trigrams a text has to have to match a needle, lower-cased the way they are indexed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigrams {
+    /// Any text may match
+    Any,
+    Trigram(u32),
+    All(Vec<Trigrams>),
+    OneOf(Vec<Trigrams>),
+}
+
+impl Trigrams {
+    /// Trigrams of any text the needle matches, as far as they can be told from its patterns
+    pub fn of_needle(needle: &GrepyNeedle) -> Self {
+        match needle {
+            GrepyNeedle::PlainText(needle) => Self::of_literal(needle.as_bytes()),
+            GrepyNeedle::Regex(regex) => Self::of_pattern(regex.as_str()),
+            // the edits may land on any trigram of the pattern
+            GrepyNeedle::Fuzzy(_) => Trigrams::Any,
+            GrepyNeedle::Query(query) => Self::of_query(query, query.root()),
+        }
+    }
+
+    fn of_query(query: &Query<regex::bytes::Regex>, node: &Node) -> Self {
+        match node {
+            Node::Term(term) => Self::of_pattern(query.terms()[*term].as_str()),
+            // the texts without the negated terms may have any trigram
+            Node::Not(_) => Trigrams::Any,
+            Node::And(left, right) => Self::all(vec![
+                Self::of_query(query, left),
+                Self::of_query(query, right),
+            ]),
+            Node::Or(left, right) => Self::one_of(vec![
+                Self::of_query(query, left),
+                Self::of_query(query, right),
+            ]),
+        }
+    }
+
+    /// Trigrams of any text the pattern matches, none at all if it can't be parsed
+    pub fn of_pattern(pattern: &str) -> Self {
+        match regex_syntax::parse(pattern) {
+            Ok(hir) => Self::of_hir(&hir),
+            Err(_) => Trigrams::Any,
+        }
+    }
+
+    fn of_literal(literal: &[u8]) -> Self {
+        Self::all(
+            trigrams(literal)
+                .into_iter()
+                .map(Trigrams::Trigram)
+                .collect(),
+        )
+    }
+
+    fn of_hir(hir: &Hir) -> Self {
+        match hir.kind() {
+            HirKind::Literal(literal) => Self::of_literal(&literal.0),
+            HirKind::Capture(capture) => Self::of_hir(&capture.sub),
+            HirKind::Repetition(repetition) if repetition.min > 0 => Self::of_hir(&repetition.sub),
+            HirKind::Concat(hirs) => Self::of_concat(hirs),
+            HirKind::Alternation(hirs) => Self::one_of(hirs.iter().map(Self::of_hir).collect()),
+            _ => Trigrams::Any,
+        }
+    }
+
+    /// Joins the literals next to each other, so the trigrams spanning them are required too
+    fn of_concat(hirs: &[Hir]) -> Self {
+        let mut required = vec![];
+        let mut literal = vec![];
+
+        for hir in hirs {
+            match (hir.kind(), folded_byte(hir)) {
+                (HirKind::Literal(part), _) => literal.extend_from_slice(&part.0),
+                (_, Some(byte)) => literal.push(byte),
+                // anchors and word boundaries match no text, so the literals around them are still adjacent
+                (HirKind::Look(_), _) => {}
+                _ => {
+                    required.push(Self::of_literal(&literal));
+                    required.push(Self::of_hir(hir));
+                    literal.clear();
+                }
+            }
+        }
+
+        required.push(Self::of_literal(&literal));
+
+        Self::all(required)
+    }
+
+    fn all(required: Vec<Trigrams>) -> Self {
+        let mut required: Vec<Trigrams> = required
+            .into_iter()
+            .flat_map(|trigrams| match trigrams {
+                Trigrams::Any => vec![],
+                Trigrams::All(required) => required,
+                trigrams => vec![trigrams],
+            })
+            .collect();
+
+        match required.len() {
+            0 => Trigrams::Any,
+            1 => required.remove(0),
+            _ => Trigrams::All(required),
+        }
+    }
+
+    fn one_of(mut alternatives: Vec<Trigrams>) -> Self {
+        if alternatives.contains(&Trigrams::Any) {
+            return Trigrams::Any;
+        }
+
+        match alternatives.len() {
+            1 => alternatives.remove(0),
+            _ => Trigrams::OneOf(alternatives),
+        }
+    }
+
+    /// Tells if the trigrams are found, given which single ones are
+    pub fn is_found(&self, found: &impl Fn(u32) -> bool) -> bool {
+        match self {
+            Trigrams::Any => true,
+            Trigrams::Trigram(trigram) => found(*trigram),
+            Trigrams::All(required) => required.iter().all(|trigrams| trigrams.is_found(found)),
+            Trigrams::OneOf(alternatives) => {
+                alternatives.iter().any(|trigrams| trigrams.is_found(found))
+            }
+        }
+    }
+}
+
+/// Trigrams of the text, with the ASCII letters lower-cased, packed into the lower bytes of integers
+fn trigrams(text: &[u8]) -> BTreeSet<u32> {
+    text.windows(3)
+        .map(|window| {
+            window.iter().fold(0, |trigram, byte| {
+                trigram << 8 | byte.to_ascii_lowercase() as u32
+            })
+        })
+        .collect()
+}
+
+/// Lower-cased byte of a class matching a single ASCII letter in either case, as `(?i)a` does.
+///
+/// The classes with other characters, such as the Kelvin sign of `(?i)k`,
+/// match text with different trigrams, so they have no byte.
+fn folded_byte(hir: &Hir) -> Option<u8> {
+    let chars: Vec<u32> = match hir.kind() {
+        HirKind::Class(Class::Unicode(class)) => class
+            .iter()
+            .flat_map(|range| range.start() as u32..=range.end() as u32)
+            .take(3)
+            .collect(),
+        HirKind::Class(Class::Bytes(class)) => class
+            .iter()
+            .flat_map(|range| range.start() as u32..=range.end() as u32)
+            .take(3)
+            .collect(),
+        _ => return None,
+    };
+
+    match chars.as_slice() {
+        &[first, second] if first < 0x80 && second < 0x80 => {
+            let (first, second) = (first as u8, second as u8);
+
+            match first.eq_ignore_ascii_case(&second) {
+                true => Some(first.to_ascii_lowercase()),
+                false => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query;
+    use std::fs::OpenOptions;
+
+    fn all(texts: &[&str]) -> Trigrams {
+        Trigrams::All(
+            texts
+                .iter()
+                .map(|text| Trigrams::of_literal(text.as_bytes()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn it_tells_the_trigrams_the_patterns_require() {
+        assert_eq!(Trigrams::of_pattern("full"), all(&["ful", "ull"]));
+        assert_eq!(Trigrams::of_pattern(r"\bdisk\b"), all(&["dis", "isk"]));
+        assert_eq!(Trigrams::of_pattern("(?i)FuLL"), all(&["ful", "ull"]));
+        assert_eq!(
+            Trigrams::of_pattern("(disk|tmpfs) full"),
+            Trigrams::All(vec![
+                Trigrams::OneOf(vec![all(&["dis", "isk"]), all(&["mpf", "pfs", "tmp"])]),
+                Trigrams::of_literal(b" fu"),
+                Trigrams::of_literal(b"ful"),
+                Trigrams::of_literal(b"ull"),
+            ])
+        );
+        assert_eq!(
+            Trigrams::of_pattern("(disk|fs) full"),
+            all(&[" fu", "ful", "ull"])
+        );
+        assert_eq!(Trigrams::of_pattern("fu.*ll"), Trigrams::Any);
+        assert_eq!(Trigrams::of_pattern("(unclosed"), Trigrams::Any);
+
+        let query = query::parse("error AND NOT timeout")
+            .unwrap()
+            .try_map(|term| regex::bytes::Regex::new(&term))
+            .unwrap();
+
+        assert_eq!(
+            Trigrams::of_needle(&GrepyNeedle::Query(&query)),
+            all(&["err", "ror", "rro"])
+        );
+    }
+
+    #[test]
+    fn it_trusts_only_the_files_unchanged_since_indexed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let full = Trigrams::of_pattern("full");
+
+        fs::write(dir.path().join("a.txt"), "disk FULL\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "all good\n").unwrap();
+        Index::build(root, &WalkOptions::default())
+            .unwrap()
+            .write(root)
+            .unwrap();
+        fs::write(dir.path().join("c.txt"), "not indexed\n").unwrap();
+
+        let index = Index::read(root).unwrap().unwrap();
+        let may_match = |name: &str| {
+            let metadata = fs::metadata(dir.path().join(name)).unwrap();
+            index.may_match(Path::new(name), &metadata, &full)
+        };
+
+        assert_eq!(index.len(), 2);
+        assert!(may_match("a.txt"));
+        assert!(!may_match("b.txt"));
+        assert!(may_match("c.txt"));
+
+        // with the same size and modification time, the change goes unnoticed
+        let path = dir.path().join("b.txt");
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        fs::write(&path, "all full\n").unwrap();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(!may_match("b.txt"));
+
+        fs::write(&path, "all full now\n").unwrap();
+        assert!(may_match("b.txt"));
+
+        let without_index = tempfile::tempdir().unwrap();
+        assert!(Index::read(without_index.path().to_str().unwrap())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::printer::Line;
+use crate::search::Stats;
+use anyhow::Result;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::Write;
+use std::ops::Range;
+
+/// Single line of the `--json` output.
+///
+/// The events of an input come in order: `begin`, then `match` and `context`
+/// as the lines are read, and `end` with the stats. Inputs with no matching
+/// lines print no events at all. Line numbers are counted from 1, and the
+/// texts come without the line endings, with the bytes which are not valid
+/// UTF-8 replaced by U+FFFD. The matches of the records searched by a single
+/// field tell where the field is, with its byte offsets within the line.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Begin {
+        path: &'a str,
+    },
+    Match {
+        path: &'a str,
+        line_number: usize,
+        /// Offset of the line's first byte in the input
+        absolute_offset: u64,
+        text: Cow<'a, str>,
+        submatches: Vec<Submatch<'a>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        field: Option<Submatch<'a>>,
+    },
+    Context {
+        path: &'a str,
+        line_number: usize,
+        absolute_offset: u64,
+        text: Cow<'a, str>,
+    },
+    End {
+        path: &'a str,
+        stats: &'a Stats,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Submatch<'a> {
+    text: Cow<'a, str>,
+    /// Byte offsets within the line
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Submatch<'a> {
+    fn new(line: &Line<'a>, span: &Range<usize>) -> Self {
+        Submatch {
+            text: String::from_utf8_lossy(&line.text[span.clone()]),
+            start: span.start,
+            end: span.end,
+        }
+    }
+}
+
+impl<'a> Event<'a> {
+    pub fn matched(path: &'a str, line: &Line<'a>, spans: &[Range<usize>]) -> Self {
+        Event::Match {
+            path,
+            line_number: line.idx + 1,
+            absolute_offset: line.offset,
+            text: String::from_utf8_lossy(line.text),
+            submatches: spans.iter().map(|span| Submatch::new(line, span)).collect(),
+            field: line.field.as_ref().map(|field| Submatch::new(line, field)),
+        }
+    }
+
+    pub fn context(path: &'a str, line: &Line<'a>) -> Self {
+        Event::Context {
+            path,
+            line_number: line.idx + 1,
+            absolute_offset: line.offset,
+            text: String::from_utf8_lossy(line.text),
+        }
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> Result<()> {
+        serde_json::to_writer(&mut *output, self)?;
+        writeln!(output)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn to_value(event: Event) -> serde_json::Value {
+        let mut output = vec![];
+        event.write(&mut output).unwrap();
+
+        assert!(output.ends_with(b"}\n"));
+
+        serde_json::from_slice(&output).unwrap()
+    }
+
+    #[test]
+    fn it_describes_the_events_schema() {
+        let line = Line {
+            idx: 14,
+            offset: 1010,
+            text: b"Pike's Peak",
+            field: None,
+            distance: None,
+        };
+        let stats = Stats {
+            lines_searched: 22,
+            bytes_searched: 1240,
+            matched_lines: 1,
+            matches: 2,
+        };
+
+        assert_eq!(
+            to_value(Event::Begin { path: "quote.md" }),
+            json!({ "type": "begin", "path": "quote.md" })
+        );
+        assert_eq!(
+            to_value(Event::matched("quote.md", &line, &[0..1, 7..8])),
+            json!({
+                "type": "match",
+                "path": "quote.md",
+                "line_number": 15,
+                "absolute_offset": 1010,
+                "text": "Pike's Peak",
+                "submatches": [
+                    { "text": "P", "start": 0, "end": 1 },
+                    { "text": "P", "start": 7, "end": 8 },
+                ],
+            })
+        );
+        assert_eq!(
+            to_value(Event::matched(
+                "quote.md",
+                &Line {
+                    field: Some(7..11),
+                    distance: None,
+                    ..line.clone()
+                },
+                &[7..8, 9..10]
+            ))["field"],
+            json!({ "text": "Peak", "start": 7, "end": 11 })
+        );
+        assert_eq!(
+            to_value(Event::context("quote.md", &line)),
+            json!({
+                "type": "context",
+                "path": "quote.md",
+                "line_number": 15,
+                "absolute_offset": 1010,
+                "text": "Pike's Peak",
+            })
+        );
+        assert_eq!(
+            to_value(Event::End {
+                path: "quote.md",
+                stats: &stats
+            }),
+            json!({
+                "type": "end",
+                "path": "quote.md",
+                "stats": {
+                    "lines_searched": 22,
+                    "bytes_searched": 1240,
+                    "matched_lines": 1,
+                    "matches": 2,
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Searches texts for patterns, the grep way.
+//!
+//! A [`Searcher`] goes through any reader line by line and tells a [`Sink`]
+//! what it finds: the matching lines, where the matches are in them, and the
+//! context lines around. The `grepy` binary is a thin wrapper printing them
+//! with a [`printer::Printer`], other tools can embed the search with a sink
+//! of their own:
+//!
+//! ```
+//! use grepy::printer::Line;
+//! use grepy::{GrepyNeedle, SearchOptions, Searcher, Sink};
+//! use std::ops::Range;
+//!
+//! /// Collects the numbers of the matching lines
+//! #[derive(Default)]
+//! struct LineNumbers(Vec<usize>);
+//!
+//! impl Sink for LineNumbers {
+//!     fn matched(
+//!         &mut self,
+//!         _name: &str,
+//!         line: &Line,
+//!         _spans: &[Range<usize>],
+//!         _replacements: &[Vec<u8>],
+//!     ) -> anyhow::Result<()> {
+//!         self.0.push(line.idx + 1);
+//!
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let needle = GrepyNeedle::PlainText("essay");
+//! let options = SearchOptions::default();
+//! let mut line_numbers = LineNumbers::default();
+//!
+//! Searcher::new(&needle, &options).process_lines(
+//!     "What should\nan essay be?\n".as_bytes(),
+//!     "quote.md",
+//!     &mut line_numbers,
+//! )?;
+//!
+//! assert_eq!(line_numbers.0, [2]);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::fuzzy::Fuzzy;
+use crate::query::Query;
+use regex::Regex;
+use std::ops::Range;
+use std::{borrow::Borrow, collections::BTreeMap};
+
+pub mod decompress;
+pub mod field;
+pub mod follow;
+pub mod fuzzy;
+pub mod index;
+pub mod json;
+pub mod pattern;
+pub mod printer;
+pub mod query;
+pub mod run;
+pub mod search;
+pub mod walk;
+
+pub use search::{SearchOptions, Searcher, Sink, Stats};
+
+/// What the lines are searched for
+pub enum GrepyNeedle<'a> {
+    /// Text matched as it is, which needs no regex at all
+    PlainText(&'a str),
+    Regex(&'a Regex),
+    /// Text matched approximately, within a number of edits
+    Fuzzy(&'a Fuzzy),
+    /// Boolean query over patterns
+    Query(&'a Query<regex::bytes::Regex>),
+}
+
+impl<'a> GrepyNeedle<'a> {
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            GrepyNeedle::PlainText(needle) => line.contains(needle),
+            GrepyNeedle::Regex(regex) => regex.is_match(line),
+            GrepyNeedle::Fuzzy(fuzzy) => fuzzy.is_match(line),
+            GrepyNeedle::Query(query) => query.is_match(line.as_bytes()),
+        }
+    }
+
+    /// Adds the byte ranges of every match in the line to the `spans`, and tells if the line matches.
+    ///
+    /// A query made of negated patterns only matches with no spans at all.
+    pub fn find_spans(&self, line: &str, spans: &mut Vec<Range<usize>>) -> bool {
+        let found = spans.len();
+
+        match self {
+            GrepyNeedle::PlainText(needle) => spans.extend(
+                line.match_indices(needle)
+                    .map(|(start, matched)| start..start + matched.len()),
+            ),
+            GrepyNeedle::Regex(regex) => {
+                spans.extend(regex.find_iter(line).map(|matched| matched.range()))
+            }
+            GrepyNeedle::Fuzzy(fuzzy) => spans.extend(
+                fuzzy
+                    .find_matches(line)
+                    .into_iter()
+                    .map(|matched| matched.span),
+            ),
+            GrepyNeedle::Query(query) => return query.find_spans(line.as_bytes(), spans),
+        }
+
+        spans.len() > found
+    }
+}
+
+/// Search over a text held in memory as a whole, see `Searcher` for streaming the inputs
+#[derive(Default)]
+pub struct Grepy<'a> {
+    /// Matches: exact lines
+    matches: BTreeMap<usize, &'a str>,
+    /// Byte ranges of every match in the matching lines
+    spans: BTreeMap<usize, Vec<Range<usize>>>,
+}
+
+impl<'a> Grepy<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Finds the matching lines, by their index
+    pub fn find_matches(
+        &mut self,
+        needle: &'a GrepyNeedle<'a>,
+        haystack: &'a str,
+    ) -> &BTreeMap<usize, &'a str> {
+        self.find_matches_extended(needle, haystack, 0)
+    }
+
+    /// Finds the matching lines, along with as many lines around each of them
+    pub fn find_matches_extended(
+        &mut self,
+        needle: &'a GrepyNeedle<'a>,
+        haystack: &'a str,
+        surrounding_lines_count: usize,
+    ) -> &BTreeMap<usize, &'a str> {
+        self.find_matches_with_context(
+            needle,
+            haystack,
+            surrounding_lines_count,
+            surrounding_lines_count,
+        )
+    }
+
+    /// Finds the matching lines, along with `before` lines preceding and `after` lines following each of them
+    pub fn find_matches_with_context(
+        &mut self,
+        needle: &'a GrepyNeedle<'a>,
+        haystack: &'a str,
+        before: usize,
+        after: usize,
+    ) -> &BTreeMap<usize, &'a str> {
+        // the lines are split once, so the context lines can be looked up right away
+        let lines: Vec<&'a str> = haystack.lines().collect();
+        // first, let's see which lines are relevant for the matching operation
+        let spans: BTreeMap<_, _> = lines
+            .iter()
+            .enumerate()
+            .filter_map(|(line_idx, line)| {
+                let mut spans = vec![];
+
+                match needle.find_spans(line, &mut spans) {
+                    true => Some((line_idx, spans)),
+                    false => None,
+                }
+            })
+            .collect();
+        let list_of_matches: BTreeMap<_, _> = spans
+            .keys()
+            .flat_map(|&line_idx| {
+                let line_from_idx = line_idx.saturating_sub(before);
+                let line_to_idx = line_idx.saturating_add(after).min(lines.len() - 1);
+
+                line_from_idx..=line_to_idx
+            })
+            // and then get the relevant lines from text
+            .map(|line_idx| (line_idx, lines[line_idx]))
+            .collect();
+
+        self.spans = spans;
+        self.matches = list_of_matches;
+
+        self.matches.borrow()
+    }
+
+    /// Where the lines found by the last search match, the context lines have no spans
+    pub fn spans(&self) -> &BTreeMap<usize, Vec<Range<usize>>> {
+        &self.spans
+    }
+}
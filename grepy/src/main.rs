@@ -1,157 +1,603 @@
-use crate::file::File as MyFile;
-use anyhow::{Error, Result};
-use clap::{App, Arg};
-use regex::Regex;
-use std::fs::File;
-use std::io::{stdin, BufRead, BufReader};
-use std::{borrow::Borrow, collections::BTreeMap};
+use anyhow::{Context, Result};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind};
+use grepy::field::FieldSelector;
+use grepy::fuzzy::Fuzzy;
+use grepy::index::{Index, Trigrams, INDEX_FILE_NAME};
+use grepy::pattern::{self, PatternOptions};
+use grepy::printer::{ColorChoice, Printer, Report};
+use grepy::query::{self, Query};
+use grepy::run::{self, Run};
+use grepy::walk::{self, WalkOptions};
+use grepy::{GrepyNeedle, SearchOptions};
+use std::io::{stdout, Write};
+use std::path::Path;
+use std::process;
 
-mod file;
+/// Exit codes, the same as grep's ones
+const EXIT_MATCH: i32 = 0;
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_ERROR: i32 = 2;
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+fn main() {
+    let args = app().try_get_matches();
 
-fn main() -> Result<()> {
-    let file_data: &[u8] = &[114, 117, 115, 116, 33];
-    let mut my_file = MyFile::new("f1.txt", file_data);
-
-    let mut data_read_buffer = vec![];
-
-    if my_file.read(&mut data_read_buffer).is_err() {
-        println!("Reading file did not go well here...");
-    }
-
-    my_file.open()?;
-
-    let my_file_length = my_file.read(&mut data_read_buffer)?;
-
-    my_file.close()?;
-
-    let text = String::from_utf8_lossy(&data_read_buffer);
+    let args = match args {
+        Ok(args) => args,
+        Err(error)
+            if error.kind == ErrorKind::DisplayHelp || error.kind == ErrorKind::DisplayVersion =>
+        {
+            print!("{}", error);
+            process::exit(EXIT_MATCH);
+        }
+        Err(error) => {
+            eprint!("{}", error);
+            process::exit(EXIT_ERROR);
+        }
+    };
 
-    println!("{:?}", my_file);
-    println!("{} is {} bytes long", &my_file.get_name(), my_file_length);
-    println!("{}", text);
+    let stdout = stdout();
 
-    return Ok(());
+    process::exit(run(&args, &mut stdout.lock()));
+}
 
-    let args = App::new("grepy")
+fn app() -> App<'static> {
+    App::new("grepy")
         .version(env!("CARGO_PKG_VERSION"))
         .about("searches for patterns")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            App::new("index")
+                .about("Manages the trigram indexes `--index` uses, search for `index` itself with `-e index`")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    App::new("build")
+                        .about("Indexes the files a search of the directory goes through, into its root")
+                        .arg(
+                            Arg::new("dir")
+                                .about("Directory to index")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                ),
+        )
         .arg(
             Arg::new("pattern")
                 .about("The pattern to search for")
                 .takes_value(true)
-                .required(true),
+                .required_unless_present("regexp"),
         )
         .arg(
             Arg::new("input")
-                .about("File to search")
+                .about("Files or directories to search, standard input when none or `-` is given")
                 .takes_value(true)
+                .multiple(true)
                 .required(false),
         )
-        .get_matches();
+        .arg(
+            Arg::new("regexp")
+                .short('e')
+                .long("regexp")
+                .about("Search for the pattern too, lines matching any of the patterns are printed")
+                .value_name("PATTERN")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("fixed-strings")
+                .short('F')
+                .long("fixed-strings")
+                .about("Take the patterns as fixed strings, not regular expressions"),
+        )
+        .arg(
+            Arg::new("ignore-case")
+                .short('i')
+                .long("ignore-case")
+                .about("Ignore case distinctions"),
+        )
+        .arg(
+            Arg::new("word-regexp")
+                .short('w')
+                .long("word-regexp")
+                .about("Match whole words only"),
+        )
+        .arg(
+            Arg::new("line-regexp")
+                .short('x')
+                .long("line-regexp")
+                .about("Match whole lines only"),
+        )
+        .arg(
+            Arg::new("fuzzy")
+                .long("fuzzy")
+                .about("Match the pattern approximately, within DISTANCE inserted, deleted, substituted or transposed characters")
+                .value_name("DISTANCE")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "regexp",
+                    "fixed-strings",
+                    "word-regexp",
+                    "line-regexp",
+                    "replace",
+                ]),
+        )
+        .arg(
+            Arg::new("query")
+                .long("query")
+                .about("Take the pattern as a query, such as `(disk OR fs) AND NOT full`, whose terms are matched as patterns")
+                .conflicts_with_all(&["regexp", "fuzzy", "replace", "multiline"]),
+        )
+        .arg(
+            Arg::new("window")
+                .long("window")
+                .about("Evaluate the query over NUM lines before and after every line, printing the lines matching its terms")
+                .value_name("NUM")
+                .takes_value(true)
+                .requires("query"),
+        )
+        .arg(
+            Arg::new("json-pointer")
+                .long("json-pointer")
+                .about("Take every line as a JSON record, matching the patterns against the value the POINTER points to, such as `/data/secret`, with its escapes decoded")
+                .value_name("POINTER")
+                .takes_value(true)
+                .conflicts_with_all(&["csv-column", "multiline", "window", "replace"]),
+        )
+        .arg(
+            Arg::new("csv-column")
+                .long("csv-column")
+                .about("Take every line as a CSV record, matching the patterns against the column the header on the first line NAMEs, with its doubled quotes decoded")
+                .value_name("NAME")
+                .takes_value(true)
+                .conflicts_with_all(&["multiline", "window", "replace"]),
+        )
+        .arg(
+            Arg::new("rank")
+                .long("rank")
+                .about("Print the matching lines of every input ranked by their distance to the fuzzy pattern, the closest first")
+                .requires("fuzzy")
+                .conflicts_with_all(&["after-context", "before-context", "context", "invert-match"]),
+        )
+        .arg(
+            Arg::new("invert-match")
+                .short('v')
+                .long("invert-match")
+                .about("Print the lines not matching"),
+        )
+        .arg(
+            Arg::new("multiline").short('U').long("multiline").about(
+                "Match the patterns against whole inputs, so the matches may span many lines",
+            ),
+        )
+        .arg(
+            Arg::new("follow")
+                .long("follow")
+                .about("Keep searching the file as it grows, like `tail -f`, reading it again once rotated")
+                .conflicts_with_all(&[
+                    "multiline",
+                    "window",
+                    "search-zip",
+                    "in-place",
+                    "rank",
+                    "count",
+                    "files-without-match",
+                ]),
+        )
+        .arg(
+            Arg::new("index")
+                .long("index")
+                .about("Skip the files the indexes of the directories searched tell can't match, searching the ones changed since they were built")
+                .conflicts_with_all(&[
+                    "invert-match",
+                    "count",
+                    "files-without-match",
+                    "search-zip",
+                    "follow",
+                ]),
+        )
+        .arg(Arg::new("search-zip").short('z').long("search-zip").about(
+            "Decompress any gzip, zstd or bzip2 input, not only the files with such an extension",
+        ))
+        .arg(
+            Arg::new("text")
+                .short('a')
+                .long("text")
+                .about("Search the binary inputs as if they were text, printing their lines"),
+        )
+        .arg(
+            Arg::new("replace")
+                .short('r')
+                .long("replace")
+                .about("Print the matching lines with the matched parts replaced by TEMPLATE, where `$1` or `${name}` stand for the capture groups")
+                .value_name("TEMPLATE")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .conflicts_with_all(&["json", "multiline"]),
+        )
+        .arg(
+            Arg::new("in-place")
+                .long("in-place")
+                .about("Rewrite the files with the replacements, printing a diff of the lines changed instead of the lines")
+                .requires("replace")
+                .conflicts_with_all(&[
+                    "invert-match",
+                    "count",
+                    "files-with-matches",
+                    "files-without-match",
+                    "quiet",
+                ]),
+        )
+        .arg(
+            Arg::new("only-matching")
+                .short('o')
+                .long("only-matching")
+                .about("Print only the matched parts of the lines, each on its own line"),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .about(
+                    "When to highlight the matches, `auto` colors the output printed to a terminal",
+                )
+                .value_name("WHEN")
+                .takes_value(true)
+                .possible_values(&ColorChoice::VALUES)
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .about("Print the results as JSON Lines, one object per event")
+                .conflicts_with_all(&[
+                    "only-matching",
+                    "count",
+                    "files-with-matches",
+                    "files-without-match",
+                    "quiet",
+                ]),
+        )
+        .arg(
+            Arg::new("count")
+                .short('c')
+                .long("count")
+                .about("Print only the number of the matching lines of every input"),
+        )
+        .arg(
+            Arg::new("files-with-matches")
+                .short('l')
+                .long("files-with-matches")
+                .about("Print only the names of the inputs with matching lines"),
+        )
+        .arg(
+            Arg::new("files-without-match")
+                .short('L')
+                .long("files-without-match")
+                .about("Print only the names of the inputs with no matching lines"),
+        )
+        .arg(
+            Arg::new("max-count")
+                .short('m')
+                .long("max-count")
+                .about("Stop reading an input after NUM matching lines")
+                .value_name("NUM")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .alias("silent")
+                .about("Print nothing, exit as soon as any line matches"),
+        )
+        .arg(
+            Arg::new("hidden")
+                .long("hidden")
+                .about("Search hidden files and directories"),
+        )
+        .arg(
+            Arg::new("no-ignore")
+                .long("no-ignore")
+                .about("Don't respect .gitignore and .ignore files"),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .about("Search only files matching the glob")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .about("Skip files and directories matching the glob")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("after-context")
+                .short('A')
+                .long("after-context")
+                .about("Print NUM lines of context after each match")
+                .value_name("NUM")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("before-context")
+                .short('B')
+                .long("before-context")
+                .about("Print NUM lines of context before each match")
+                .value_name("NUM")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("context")
+                .short('C')
+                .long("context")
+                .about("Print NUM lines of context around each match")
+                .value_name("NUM")
+                .takes_value(true),
+        )
+}
 
-    let arg_pattern = args.value_of("pattern").expect("arg must be provided");
-    let search_term = Regex::new(arg_pattern)?;
-    let grepy_needle = GrepyNeedle::Regex(&search_term);
+/// Searches all the inputs, reporting the errors on the way, and tells the exit code
+fn run<W: Write>(args: &ArgMatches, output: &mut W) -> i32 {
+    if let Some(("index", index_args)) = args.subcommand() {
+        return run_index(index_args, output);
+    }
 
-    match args.value_of("input") {
-        Some(arg_input) => {
-            let input_file = File::open(arg_input)?;
+    let mut paths: Vec<&str> = args
+        .values_of("input")
+        .map(|paths| paths.collect())
+        .unwrap_or_default();
+    let patterns: Vec<&str> = match args.values_of("regexp") {
+        Some(patterns) => {
+            // with the patterns given by `-e`, the first positional argument is an input
+            if let Some(path) = args.value_of("pattern") {
+                paths.insert(0, path);
+            }
 
-            process_lines(BufReader::new(input_file), grepy_needle)
+            patterns.collect()
+        }
+        None => vec![args.value_of("pattern").expect("arg must be provided")],
+    };
+    let pattern_options = PatternOptions {
+        ignore_case: args.is_present("ignore-case"),
+        word: args.is_present("word-regexp"),
+        line: args.is_present("line-regexp"),
+        fixed_strings: args.is_present("fixed-strings"),
+    };
+    let fuzzy = match count(args, "fuzzy") {
+        Ok(max_distance) => max_distance
+            .map(|max_distance| Fuzzy::new(patterns[0], max_distance, pattern_options.ignore_case)),
+        Err(error) => {
+            eprintln!("grepy: {:#}", error);
+            return EXIT_ERROR;
+        }
+    };
+    let query = match args.is_present("query") {
+        true => match compile_query(patterns[0], &pattern_options) {
+            Ok(query) => Some(query),
+            Err(error) => {
+                eprintln!("grepy: {}", error);
+                return EXIT_ERROR;
+            }
+        },
+        false => None,
+    };
+    // the fuzzy pattern is taken literally, and the query has a regex for every term, so there is none to compile
+    let search_term = match (&fuzzy, &query) {
+        (Some(_), _) | (_, Some(_)) => None,
+        (None, None) => match pattern::compile(&patterns, &pattern_options) {
+            Ok(search_term) => Some(search_term),
+            Err(error) => {
+                eprintln!("grepy: {}", error);
+                return EXIT_ERROR;
+            }
+        },
+    };
+    let grepy_needle = match (&fuzzy, &query, &search_term) {
+        (Some(fuzzy), _, _) => GrepyNeedle::Fuzzy(fuzzy),
+        (_, Some(query), _) => GrepyNeedle::Query(query),
+        _ if pattern_options.is_plain_text(&patterns) => GrepyNeedle::PlainText(patterns[0]),
+        (_, _, Some(search_term)) => GrepyNeedle::Regex(search_term),
+        (_, _, None) => unreachable!("the regex is compiled unless matching fuzzily or by a query"),
+    };
+    let rank = fuzzy.is_some() && args.is_present("rank");
+    let search_options = match search_options(args) {
+        Ok(search_options) => search_options,
+        Err(error) => {
+            eprintln!("grepy: {:#}", error);
+            return EXIT_ERROR;
         }
-        None => process_lines(stdin().lock(), grepy_needle),
+    };
+
+    let walk_options = WalkOptions {
+        hidden: args.is_present("hidden"),
+        no_ignore: args.is_present("no-ignore"),
+        include: values(args, "include"),
+        exclude: values(args, "exclude"),
+    };
+
+    let mut run = Run {
+        needle: &grepy_needle,
+        options: &search_options,
+        printer: printer(args),
+        search_zip: args.is_present("search-zip"),
+        in_place: args.is_present("in-place"),
+        rank,
+    };
+
+    if args.is_present("follow") {
+        // the file never ends, so its lines get printed as they are found rather than once it has been searched
+        return match run.follow(&paths, output) {
+            Ok(true) => EXIT_MATCH,
+            Ok(false) => EXIT_NO_MATCH,
+            Err(error) => {
+                eprintln!("grepy: {:#}", error);
+                EXIT_ERROR
+            }
+        };
+    }
+
+    let inputs = walk::inputs(&paths, &walk_options);
+    let inputs = match args.is_present("index") {
+        true => run::narrow_inputs(
+            inputs,
+            &paths,
+            &Trigrams::of_needle(&grepy_needle),
+            |error| eprintln!("grepy: {:#}, searching without the index", error),
+        ),
+        false => inputs,
+    };
+    let report = run.printer.report;
+    // the JSON events and the lists of files always tell the inputs they come from
+    run.printer.with_file_name = run.printer.json
+        || report == Report::FilesWithMatches
+        || report == Report::FilesWithoutMatch
+        || inputs.len() > 1
+        || paths.iter().any(|path| Path::new(path).is_dir());
+
+    let mut any_error = false;
+    let any_match = run.search(inputs, output, |error| {
+        any_error = true;
+        eprintln!("grepy: {:#}", error);
+    });
+
+    match (any_error, any_match) {
+        // grep does the same, as the errors can't change the answer
+        (true, true) if report == Report::Quiet => EXIT_MATCH,
+        (true, _) => EXIT_ERROR,
+        (false, true) => EXIT_MATCH,
+        (false, false) => EXIT_NO_MATCH,
     }
 }
 
-fn process_lines<T: BufRead>(reader: T, grepy_needle: GrepyNeedle) -> Result<()> {
-    for (line_idx, line_) in reader.lines().enumerate() {
-        let line = line_?;
-        let mut grepy = Grepy::new();
-        let matches = grepy.find_matches(&grepy_needle, &line);
+fn run_index<W: Write>(args: &ArgMatches, output: &mut W) -> i32 {
+    let built = match args.subcommand() {
+        Some(("build", build_args)) => build_index(
+            build_args.value_of("dir").expect("arg must be provided"),
+            output,
+        ),
+        _ => unreachable!("a subcommand is required"),
+    };
 
-        if matches.is_empty() == false {
-            println!("#{}: {}", line_idx, line);
+    match built {
+        Ok(()) => EXIT_MATCH,
+        Err(error) => {
+            eprintln!("grepy: {:#}", error);
+            EXIT_ERROR
         }
     }
+}
+
+/// Writes the index of the directory, telling how many files it has
+fn build_index<W: Write>(dir: &str, output: &mut W) -> Result<()> {
+    let index = Index::build(dir, &WalkOptions::default())
+        .and_then(|index| index.write(dir).map(|_| index))
+        .with_context(|| dir.to_owned())?;
+
+    writeln!(
+        output,
+        "{}: {} files indexed",
+        Path::new(dir).join(INDEX_FILE_NAME).display(),
+        index.len()
+    )?;
 
     Ok(())
 }
 
-enum GrepyNeedle<'a> {
-    PlainText(&'a str),
-    Regex(&'a Regex),
-}
+fn search_options(args: &ArgMatches) -> Result<SearchOptions> {
+    let context = count(args, "context")?.unwrap_or(0);
 
-#[derive(Default)]
-struct Grepy<'a> {
-    /// Matches: exact lines
-    matches: BTreeMap<usize, &'a str>,
+    Ok(SearchOptions {
+        before_context: count(args, "before-context")?.unwrap_or(context),
+        after_context: count(args, "after-context")?.unwrap_or(context),
+        invert: args.is_present("invert-match"),
+        max_count: count(args, "max-count")?.map(|max_count| max_count as u64),
+        multiline: args.is_present("multiline"),
+        text: args.is_present("text"),
+        replace: args
+            .value_of("replace")
+            .map(|template| template.as_bytes().to_vec()),
+        query_window: count(args, "window")?.unwrap_or(0),
+        field: field_selector(args)?,
+    })
 }
 
-impl<'a> Grepy<'a> {
-    fn new() -> Self {
-        Default::default()
+/// Field of the records the patterns are matched against, if one is selected
+fn field_selector(args: &ArgMatches) -> Result<Option<FieldSelector>> {
+    match (args.value_of("json-pointer"), args.value_of("csv-column")) {
+        (Some(pointer), _) => FieldSelector::json_pointer(pointer).map(Some),
+        (None, Some(column)) => Ok(Some(FieldSelector::CsvColumn(column.to_owned()))),
+        (None, None) => Ok(None),
     }
+}
 
-    fn find_matches(
-        &mut self,
-        needle: &'a GrepyNeedle<'a>,
-        haystack: &'a str,
-    ) -> &BTreeMap<usize, &'a str> {
-        self.find_matches_extended(needle, haystack, 0)
-    }
+/// Parses the query, and compiles each of its terms as a pattern
+fn compile_query(query: &str, options: &PatternOptions) -> Result<Query<regex::bytes::Regex>> {
+    let query = query::parse(query)?;
 
-    fn find_matches_extended(
-        &mut self,
-        needle: &'a GrepyNeedle<'a>,
-        haystack: &'a str,
-        surrounding_lines_count: usize,
-    ) -> &BTreeMap<usize, &'a str> {
-        let list_of_matches: BTreeMap<_, _> = haystack
-            .lines()
-            .enumerate()
-            // first, let's see which lines are relevant for the matching operation
-            .filter_map(|(line_idx, line)| {
-                let no_match_in_current_line = match needle {
-                    GrepyNeedle::PlainText(needle) => line.contains(needle) == false,
-                    GrepyNeedle::Regex(regex) => (*regex).find(line).is_none(),
-                };
-
-                if no_match_in_current_line {
-                    return None;
-                }
-
-                let matched_line = match surrounding_lines_count {
-                    0 => vec![line_idx],
-                    _ => {
-                        let line_from_idx = line_idx.saturating_sub(surrounding_lines_count);
-                        let line_to_idx = line_idx.saturating_add(surrounding_lines_count);
-
-                        (line_from_idx..=line_to_idx).collect::<Vec<_>>()
-                    }
-                };
-
-                Some(matched_line)
-            })
-            .flatten()
-            // and then get the relevant lines from text
-            .filter_map(|line_idx| match haystack.lines().nth(line_idx) {
-                None => None,
-                Some(line) => Some((line_idx, line)),
-            })
-            .collect();
+    Ok(query.try_map(|term| pattern::compile_bytes(&[&term], options))?)
+}
 
-        self.matches = list_of_matches;
+fn printer(args: &ArgMatches) -> Printer {
+    Printer {
+        // known only once the inputs are
+        with_file_name: false,
+        color: ColorChoice::parse(args.value_of("color").unwrap_or("auto"))
+            .expect("value must be one of the possible ones")
+            .use_color(),
+        only_matching: args.is_present("only-matching"),
+        json: args.is_present("json"),
+        report: report(args),
+    }
+}
 
-        self.matches.borrow()
+fn report(args: &ArgMatches) -> Report {
+    // the same precedence as grep's one
+    if args.is_present("quiet") {
+        Report::Quiet
+    } else if args.is_present("files-with-matches") {
+        Report::FilesWithMatches
+    } else if args.is_present("files-without-match") {
+        Report::FilesWithoutMatch
+    } else if args.is_present("count") {
+        Report::Count
+    } else {
+        Report::Lines
     }
 }
 
+fn count(args: &ArgMatches, name: &str) -> Result<Option<usize>> {
+    args.value_of(name)
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("invalid --{} value: {}", name, value))
+        })
+        .transpose()
+}
+
+fn values(args: &ArgMatches, name: &str) -> Vec<String> {
+    args.values_of(name)
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use grepy::decompress::decompress;
+    use grepy::{Grepy, Searcher};
+    use regex::Regex;
+    use std::fs;
+
+    fn run_args(args: &[&str]) -> (i32, String) {
+        let mut output = vec![];
+        let exit_code = run(&app().try_get_matches_from(args).unwrap(), &mut output);
+
+        (exit_code, String::from_utf8(output).unwrap())
+    }
 
     #[test]
     fn it_finds_results_by_exact_match_when_available() {
@@ -192,8 +638,8 @@ mod test {
             grepy.find_matches_extended(&grepy_needle, THE_QUOTE, surrounding_lines_count);
 
         let matched_lines: String = matches
-            .iter()
-            .map(|(_, s)| &**s)
+            .values()
+            .map(|s| &**s)
             .collect::<Vec<_>>()
             .join("\n");
 
@@ -218,10 +664,779 @@ merely somewhere in Colorado. But if I say it's in the exact middle of Colorado,
 
         assert_eq!(matches.len(), 9);
     }
-}
 
-/// Source: http://www.paulgraham.com/useful.html
-const THE_QUOTE: &str = "\
+    #[test]
+    fn it_prints_matching_lines_and_tells_if_there_were_any() {
+        let search_term = Regex::new("Colorado").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+        let mut output = vec![];
+
+        let options = SearchOptions::default();
+
+        assert!(Searcher::new(&grepy_needle, &options)
+            .process_lines(
+                THE_QUOTE.as_bytes(),
+                "quote.md",
+                &mut Printer::default().sink(&mut output)
+            )
+            .unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+16:merely somewhere in Colorado. But if I say it's in the exact middle of Colorado,
+"
+        );
+
+        let search_term = Regex::new("Utah").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+
+        assert!(!Searcher::new(&grepy_needle, &options)
+            .process_lines(
+                THE_QUOTE.as_bytes(),
+                "quote.md",
+                &mut Printer::default().sink(vec![])
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn it_prints_context_lines_between_group_separators() {
+        let search_term = Regex::new("essay|Colorado than").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+        let options = SearchOptions {
+            before_context: 1,
+            after_context: 2,
+            ..Default::default()
+        };
+        let printer = Printer {
+            with_file_name: true,
+            ..Default::default()
+        };
+        let mut output = vec![];
+
+        Searcher::new(&grepy_needle, &options)
+            .process_lines(
+                THE_QUOTE.as_bytes(),
+                "quote.md",
+                &mut printer.sink(&mut output),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+quote.md:1:What should an essay be?
+quote.md-2-
+quote.md:3:Many people would say persuasive. That's what a lot of us were taught essays should be.
+quote.md:4:But I think we can aim for something more ambitious: that an essay should be useful.
+quote.md-5-
+quote.md-6-To start with, that means it should be correct. But it's not enough merely to be correct.
+--
+quote.md-14-
+quote.md:15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+quote.md-16-merely somewhere in Colorado. But if I say it's in the exact middle of Colorado,
+quote.md-17-I've now gone too far, because it's a bit east of the middle.
+"
+        );
+    }
+
+    #[test]
+    fn it_includes_asymmetric_context_when_needed() {
+        let mut grepy = Grepy::new();
+        let grepy_needle = GrepyNeedle::PlainText("Pike");
+        let matches = grepy.find_matches_with_context(&grepy_needle, THE_QUOTE, 0, 2);
+
+        assert_eq!(
+            matches.keys().copied().collect::<Vec<_>>(),
+            vec![14, 15, 16]
+        );
+    }
+
+    #[test]
+    fn it_exits_with_grep_compatible_codes() {
+        let exit_code = |args: &[&str]| run_args(args).0;
+
+        assert_eq!(exit_code(&["grepy", "essay", "input/quote.md"]), EXIT_MATCH);
+        assert_eq!(
+            exit_code(&["grepy", "Utah", "input/quote.md"]),
+            EXIT_NO_MATCH
+        );
+        assert_eq!(
+            exit_code(&["grepy", "essay", "input/quote.md", "input/missing.md"]),
+            EXIT_ERROR
+        );
+        assert_eq!(
+            exit_code(&["grepy", "(essay", "input/quote.md"]),
+            EXIT_ERROR
+        );
+    }
+
+    #[test]
+    fn it_prefixes_matches_with_file_names_when_searching_many_files() {
+        let mut output = vec![];
+        let args = app()
+            .try_get_matches_from(["grepy", "Colorado than", "input"])
+            .unwrap();
+
+        assert_eq!(run(&args, &mut output), EXIT_MATCH);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+input/quote.md:15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+input/quote.md.bz2:15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+input/quote.md.gz:15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+input/quote.md.zst:15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+"
+        );
+    }
+
+    #[test]
+    fn it_decompresses_the_standard_input_only_when_forced() {
+        let search_term = Regex::new("essay").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+        let compressed = std::fs::read("input/quote.md.zst").unwrap();
+        let count = |search_zip| {
+            let options = SearchOptions::default();
+            let printer = Printer {
+                report: Report::Count,
+                ..Default::default()
+            };
+            let reader = decompress(compressed.as_slice(), None, search_zip).unwrap();
+            let mut output = vec![];
+
+            Searcher::new(&grepy_needle, &options)
+                .process_lines(reader, "-", &mut printer.sink(&mut output))
+                .unwrap();
+
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(count(false), "0\n");
+        assert_eq!(count(true), "3\n");
+    }
+
+    fn matched_lines(patterns: &[&str], options: &PatternOptions) -> Vec<usize> {
+        let search_term = pattern::compile(patterns, options).unwrap();
+        let grepy_needle = match options.is_plain_text(patterns) {
+            true => GrepyNeedle::PlainText(patterns[0]),
+            false => GrepyNeedle::Regex(&search_term),
+        };
+        let mut grepy = Grepy::new();
+
+        grepy
+            .find_matches(&grepy_needle, THE_QUOTE)
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn it_ignores_case_when_asked_to() {
+        let options = PatternOptions {
+            ignore_case: true,
+            ..Default::default()
+        };
+
+        assert!(matched_lines(&["colorado"], &PatternOptions::default()).is_empty());
+        assert_eq!(matched_lines(&["colorado"], &options), vec![14, 15]);
+    }
+
+    #[test]
+    fn it_matches_whole_words_only_when_asked_to() {
+        let options = PatternOptions {
+            word: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            matched_lines(&["essay"], &PatternOptions::default()),
+            vec![0, 2, 3]
+        );
+        assert_eq!(matched_lines(&["essay"], &options), vec![0, 3]);
+    }
+
+    #[test]
+    fn it_matches_whole_lines_only_when_asked_to() {
+        let options = PatternOptions {
+            line: true,
+            ..Default::default()
+        };
+
+        assert!(matched_lines(&["Useful writing"], &options).is_empty());
+        assert_eq!(matched_lines(&["Useful writing.*"], &options), vec![21]);
+    }
+
+    #[test]
+    fn it_takes_fixed_strings_literally() {
+        let options = PatternOptions {
+            fixed_strings: true,
+            ..Default::default()
+        };
+
+        assert!(options.is_plain_text(&["Colorado."]));
+        assert_eq!(
+            matched_lines(&["Colorado."], &PatternOptions::default()),
+            vec![14, 15]
+        );
+        assert_eq!(matched_lines(&["Colorado."], &options), vec![15]);
+        assert!(matched_lines(&["(essay"], &options).is_empty());
+    }
+
+    #[test]
+    fn it_matches_any_of_many_patterns() {
+        let options = PatternOptions {
+            ignore_case: true,
+            fixed_strings: true,
+            ..Default::default()
+        };
+
+        assert!(!options.is_plain_text(&["pike"]));
+        assert_eq!(
+            matched_lines(&["Pike", "ambitious"], &PatternOptions::default()),
+            vec![3, 14]
+        );
+        assert_eq!(matched_lines(&["pike", "ambitious."], &options), vec![14]);
+    }
+
+    #[test]
+    fn it_prints_the_lines_not_matching_when_inverted() {
+        let search_term = Regex::new("e").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+        let options = SearchOptions {
+            invert: true,
+            ..Default::default()
+        };
+        let mut output = vec![];
+
+        Searcher::new(&grepy_needle, &options)
+            .process_lines(
+                THE_QUOTE.as_bytes(),
+                "quote.md",
+                &mut Printer::default().sink(&mut output),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2:\n5:\n11:\n14:\n18:\n"
+        );
+    }
+
+    #[test]
+    fn it_takes_the_first_positional_argument_as_input_with_patterns_given_by_flags() {
+        let mut output = vec![];
+        let args = app()
+            .try_get_matches_from([
+                "grepy",
+                "-w",
+                "-e",
+                "pike",
+                "-e",
+                "ambitious",
+                "-i",
+                "input/quote.md",
+            ])
+            .unwrap();
+
+        assert_eq!(run(&args, &mut output), EXIT_MATCH);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+4:But I think we can aim for something more ambitious: that an essay should be useful.
+15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+"
+        );
+    }
+
+    #[test]
+    fn it_finds_byte_ranges_of_every_match() {
+        let mut grepy = Grepy::new();
+        let search_term = Regex::new("Colorado|middle").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+
+        grepy.find_matches_extended(&grepy_needle, THE_QUOTE, 1);
+
+        let spans = grepy.spans();
+
+        assert_eq!(spans.keys().copied().collect::<Vec<_>>(), vec![14, 15, 16]);
+        assert_eq!(spans[&14], vec![66..72, 76..84]);
+        assert_eq!(
+            &THE_QUOTE.lines().nth(15).unwrap()[spans[&15][2].clone()],
+            "Colorado"
+        );
+
+        let grepy_needle = GrepyNeedle::PlainText("Colorado");
+
+        assert_eq!(grepy.find_matches(&grepy_needle, THE_QUOTE).len(), 2);
+        assert_eq!(grepy.spans()[&15], vec![20..28, 71..79]);
+    }
+
+    #[test]
+    fn it_highlights_matches_and_prints_only_them_when_asked_to() {
+        let output = |args: &[&str]| run_args(args).1;
+
+        assert_eq!(
+            output(&["grepy", "--color=always", "Pike", "input/quote.md"]),
+            "\x1b[32m15\x1b[0m\x1b[36m:\x1b[0mFor example, it's more useful to say that \x1b[1;31mPike\x1b[0m's Peak is near the middle of Colorado than\n"
+        );
+        assert_eq!(
+            output(&["grepy", "-o", "-i", "co[a-z]+do", "input/quote.md"]),
+            "15:Colorado\n16:Colorado\n16:Colorado\n"
+        );
+        assert_eq!(
+            output(&["grepy", "--color=never", "-v", "-o", "e", "input/quote.md"]),
+            ""
+        );
+    }
+
+    #[test]
+    fn it_prints_json_events_of_inputs_with_matches() {
+        let mut output = vec![];
+        let args = app()
+            .try_get_matches_from([
+                "grepy",
+                "--json",
+                "-A1",
+                "Pike",
+                "input/quote.md",
+                "input/missing.md",
+            ])
+            .unwrap();
+
+        assert_eq!(run(&args, &mut output), EXIT_ERROR);
+
+        let quote = std::fs::read_to_string("input/quote.md").unwrap();
+        let events: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event["type"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["begin", "match", "context", "end"]
+        );
+        assert!(events.iter().all(|event| event["path"] == "input/quote.md"));
+
+        for event in &events[1..3] {
+            let offset = event["absolute_offset"].as_u64().unwrap() as usize;
+
+            assert!(quote[offset..].starts_with(event["text"].as_str().unwrap()));
+        }
+
+        assert_eq!(events[1]["line_number"], 15);
+        assert_eq!(events[1]["submatches"][0]["text"], "Pike");
+        assert_eq!(events[3]["stats"]["lines_searched"], 22);
+        assert_eq!(events[3]["stats"]["bytes_searched"], quote.len());
+    }
+
+    #[test]
+    fn it_counts_and_lists_the_inputs_instead_of_printing_lines() {
+        assert_eq!(
+            run_args(&["grepy", "-c", "Colorado", "input/quote.md"]),
+            (EXIT_MATCH, "2\n".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "-c", "-m1", "Colorado", "input/quote.md"]),
+            (EXIT_MATCH, "1\n".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "-c", "Utah", "input/quote.md", "input/quote.md"]),
+            (EXIT_NO_MATCH, "input/quote.md:0\ninput/quote.md:0\n".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "-l", "Colorado", "input/quote.md"]),
+            (EXIT_MATCH, "input/quote.md\n".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "-L", "Colorado", "input/quote.md"]),
+            (EXIT_NO_MATCH, "".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "-L", "Utah", "input/quote.md"]),
+            (EXIT_MATCH, "input/quote.md\n".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "-q",
+                "Colorado",
+                "input/missing.md",
+                "input/quote.md"
+            ]),
+            (EXIT_MATCH, "".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "-m1", "-A1", "Colorado", "input/quote.md"]),
+            (
+                EXIT_MATCH,
+                "\
+15:For example, it's more useful to say that Pike's Peak is near the middle of Colorado than
+16-merely somewhere in Colorado. But if I say it's in the exact middle of Colorado,
+"
+                .into()
+            )
+        );
+    }
+
+    #[test]
+    fn it_stops_reading_once_the_answer_is_known() {
+        let search_term = Regex::new("essay").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+        let lines_searched = |options: SearchOptions, report| {
+            let printer = Printer {
+                report,
+                ..Default::default()
+            };
+            let mut searcher = Searcher::new(&grepy_needle, &options);
+
+            searcher
+                .process_lines(THE_QUOTE.as_bytes(), "quote.md", &mut printer.sink(vec![]))
+                .unwrap();
+            searcher.stats().lines_searched
+        };
+
+        assert_eq!(lines_searched(Default::default(), Report::Count), 22);
+        assert_eq!(lines_searched(Default::default(), Report::Quiet), 1);
+        assert_eq!(
+            lines_searched(Default::default(), Report::FilesWithMatches),
+            1
+        );
+        assert_eq!(
+            lines_searched(Default::default(), Report::FilesWithoutMatch),
+            1
+        );
+        assert_eq!(
+            lines_searched(
+                SearchOptions {
+                    max_count: Some(2),
+                    ..Default::default()
+                },
+                Report::Lines
+            ),
+            3
+        );
+    }
+
+    #[test]
+    fn it_matches_fuzzily_and_ranks_the_lines_by_distance() {
+        assert_eq!(
+            run_args(&["grepy", "--fuzzy", "1", "-o", "essays", "input/quote.md"]),
+            (EXIT_MATCH, "1:essay\n3:essays\n4:essay\n".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--fuzzy=1",
+                "--rank",
+                "-o",
+                "essays",
+                "input/quote.md"
+            ]),
+            (EXIT_MATCH, "3:essays\n1:essay\n4:essay\n".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--fuzzy",
+                "1",
+                "--color=always",
+                "Pkie",
+                "input/quote.md"
+            ]),
+            (
+                EXIT_MATCH,
+                "\x1b[32m15\x1b[0m\x1b[36m:\x1b[0mFor example, it's more useful to say that \
+\x1b[1;31mPike\x1b[0m's Peak is near the middle of Colorado than\n"
+                    .into()
+            )
+        );
+        assert_eq!(
+            run_args(&["grepy", "--fuzzy", "1", "[Utah]", "input/quote.md"]),
+            (EXIT_NO_MATCH, "".into())
+        );
+    }
+
+    #[test]
+    fn it_matches_the_lines_a_query_holds_for_alone_or_with_the_lines_around() {
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--query",
+                "-o",
+                "correct AND NOT (But OR doubt)",
+                "input/quote.md"
+            ]),
+            (EXIT_MATCH, "7:correct\n19:correct\n".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--query",
+                "-oi",
+                "(pike OR utah) AND colorado",
+                "input/quote.md"
+            ]),
+            (EXIT_MATCH, "15:Pike\n15:Colorado\n".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--query",
+                "--window",
+                "1",
+                "-o",
+                "Colorado AND Pike",
+                "input/quote.md"
+            ]),
+            (
+                EXIT_MATCH,
+                "15:Pike\n15:Colorado\n16:Colorado\n16:Colorado\n".into()
+            )
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--query",
+                "-o",
+                "Colorado AND NOT Pike",
+                "input/quote.md"
+            ]),
+            (EXIT_MATCH, "16:Colorado\n16:Colorado\n".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--query",
+                "--window=1",
+                "Colorado AND NOT Pike",
+                "input/quote.md"
+            ]),
+            (EXIT_NO_MATCH, "".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--query",
+                "error AND AND timeout",
+                "input/quote.md"
+            ]),
+            (EXIT_ERROR, "".into())
+        );
+    }
+
+    #[test]
+    fn it_matches_a_single_field_of_json_and_csv_records() {
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--json-pointer",
+                "/data/secret",
+                "123",
+                "input/subscriptions.jsonl"
+            ]),
+            (
+                EXIT_MATCH,
+                "\
+1:{\"suscription_id\": 1, \"data\": {\"secret\": 123}}\t123
+4:{\"suscription_id\": 123, \"data\": {\"secret\": 4123}}\t4123
+"
+                .into()
+            )
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--json-pointer=/data/secret",
+                "-o",
+                "-x",
+                "[a-z0-9]+",
+                "input/subscriptions.jsonl"
+            ]),
+            (EXIT_MATCH, "1:123\n2:s3cr3t\n4:4123\n".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--csv-column",
+                "secret",
+                "123",
+                "input/keyrings.csv"
+            ]),
+            (
+                EXIT_MATCH,
+                "2:1,\"Doe, John\",123\t123\n5:4,\"Smith, 123\",9123\t9123\n".into()
+            )
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--csv-column",
+                "owner",
+                "Rick",
+                "input/keyrings.csv"
+            ]),
+            (
+                EXIT_MATCH,
+                "4:3,\"Roe, \"\"Rick\"\"\",\tRoe, \"\"Rick\"\"\n".into()
+            )
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--csv-column",
+                "secrets",
+                "1",
+                "input/keyrings.csv"
+            ]),
+            (EXIT_ERROR, "".into())
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "--json-pointer",
+                "data/secret",
+                "1",
+                "input/subscriptions.jsonl"
+            ]),
+            (EXIT_ERROR, "".into())
+        );
+    }
+
+    #[test]
+    fn it_matches_the_values_of_the_fields_with_their_escapes_decoded() {
+        let dir = tempfile::tempdir().unwrap();
+        let jsonl = dir.path().join("messages.jsonl");
+        let csv = dir.path().join("messages.csv");
+        fs::write(
+            &jsonl,
+            "{\"m\":\"say \\\"hi\\\"\"}\n{\"m\":\"caf\\u00e9\"}\n",
+        )
+        .unwrap();
+        fs::write(&csv, "id,m\n1,\"say \"\"hi\"\"\"\n").unwrap();
+
+        let jsonl = jsonl.to_str().unwrap();
+        let csv = csv.to_str().unwrap();
+
+        assert_eq!(
+            run_args(&["grepy", "--json-pointer", "/m", "-o", "hi\"", jsonl]),
+            (EXIT_MATCH, "1:hi\\\"\n".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "--json-pointer", "/m", "-o", "café", jsonl]),
+            (EXIT_MATCH, "2:caf\\u00e9\n".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "--csv-column", "m", "-o", "\"hi\"", csv]),
+            (EXIT_MATCH, "2:\"\"hi\"\"\n".into())
+        );
+    }
+
+    #[test]
+    fn it_follows_only_a_single_file() {
+        let exit_code = |args: &[&str]| run_args(args).0;
+
+        assert_eq!(
+            exit_code(&["grepy", "--follow", "Colorado", "input"]),
+            EXIT_ERROR
+        );
+        assert_eq!(
+            exit_code(&[
+                "grepy",
+                "--follow",
+                "Colorado",
+                "input/quote.md",
+                "input/quote.md.gz"
+            ]),
+            EXIT_ERROR
+        );
+        assert_eq!(
+            exit_code(&["grepy", "--follow", "Colorado", "-"]),
+            EXIT_ERROR
+        );
+        assert_eq!(
+            exit_code(&["grepy", "--follow", "Colorado", "input/missing.md"]),
+            EXIT_ERROR
+        );
+    }
+
+    #[test]
+    fn it_narrows_the_files_searched_with_the_index_built() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("a.txt"), "disk full\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "all good\n").unwrap();
+
+        assert_eq!(
+            run_args(&["grepy", "index", "build", root]),
+            (
+                EXIT_MATCH,
+                format!(
+                    "{}: 2 files indexed\n",
+                    dir.path().join(INDEX_FILE_NAME).display()
+                )
+            )
+        );
+        assert_eq!(
+            run_args(&["grepy", "--index", "-i", "FULL", root]),
+            (
+                EXIT_MATCH,
+                format!("{}:1:disk full\n", dir.path().join("a.txt").display())
+            )
+        );
+
+        // changed since the index was built, so searched anyway
+        fs::write(dir.path().join("b.txt"), "all full now\n").unwrap();
+
+        assert_eq!(
+            run_args(&["grepy", "--index", "full", root]),
+            (
+                EXIT_MATCH,
+                format!(
+                    "{}:1:disk full\n{}:1:all full now\n",
+                    dir.path().join("a.txt").display(),
+                    dir.path().join("b.txt").display()
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn it_prints_every_line_a_multiline_match_touches() {
+        assert_eq!(
+            run_args(&["grepy", r"useful\.\n\nTo", "input/quote.md"]),
+            (EXIT_NO_MATCH, "".into())
+        );
+        assert_eq!(
+            run_args(&["grepy", "-U", r"useful\.\n\nTo", "input/quote.md"]),
+            (
+                EXIT_MATCH,
+                "\
+4:But I think we can aim for something more ambitious: that an essay should be useful.
+5:
+6:To start with, that means it should be correct. But it's not enough merely to be correct.
+"
+                .into()
+            )
+        );
+        assert_eq!(
+            run_args(&[
+                "grepy",
+                "-U",
+                "-o",
+                r"(?mUi)considered,\n.*mistake",
+                "input/quote.md"
+            ]),
+            (EXIT_MATCH, "9:considered,\n10:that it's a mistake\n".into())
+        );
+    }
+
+    /// Source: http://www.paulgraham.com/useful.html
+    const THE_QUOTE: &str = "\
 What should an essay be?
 
 Many people would say persuasive. That's what a lot of us were taught essays should be.
@@ -244,3 +1459,4 @@ Precision and correctness are like opposing forces.
 It's easy to satisfy one if you ignore the other.
 The converse of vaporous academic writing is the bold, but false, rhetoric of demagogues.
 Useful writing is bold, but true.";
+}
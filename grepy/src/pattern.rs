@@ -0,0 +1,55 @@
+use regex::Regex;
+
+/// Tells how the patterns given on the command line are matched
+#[derive(Clone, Debug, Default)]
+pub struct PatternOptions {
+    pub ignore_case: bool,
+    /// Match whole words only
+    pub word: bool,
+    /// Match whole lines only
+    pub line: bool,
+    /// Take the patterns literally rather than as regular expressions
+    pub fixed_strings: bool,
+}
+
+impl PatternOptions {
+    /// A single fixed string searched as it is does not need a regex at all
+    pub fn is_plain_text(&self, patterns: &[&str]) -> bool {
+        self.fixed_strings && patterns.len() == 1 && !self.ignore_case && !self.word && !self.line
+    }
+}
+
+/// Compiles the patterns into a single regex, matching a line if any of the patterns does
+pub fn compile(patterns: &[&str], options: &PatternOptions) -> Result<Regex, regex::Error> {
+    let alternatives: Vec<String> = patterns
+        .iter()
+        .map(|pattern| match options.fixed_strings {
+            true => format!("(?:{})", regex::escape(pattern)),
+            false => format!("(?:{})", pattern),
+        })
+        .collect();
+    let mut pattern = alternatives.join("|");
+
+    if options.word {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+
+    if options.line {
+        pattern = format!("^(?:{})$", pattern);
+    }
+
+    // the flag goes in the pattern itself, so the regex rebuilt from it for bytes keeps it
+    if options.ignore_case {
+        pattern = format!("(?i){}", pattern);
+    }
+
+    Regex::new(&pattern)
+}
+
+/// Compiles the patterns the same, into a regex matching bytes, such as the terms of a query
+pub fn compile_bytes(
+    patterns: &[&str],
+    options: &PatternOptions,
+) -> Result<regex::bytes::Regex, regex::Error> {
+    regex::bytes::Regex::new(compile(patterns, options)?.as_str())
+}
@@ -0,0 +1,528 @@
+use crate::json::Event;
+use crate::search::{ReplacedLine, Sink, Stats};
+use anyhow::Result;
+use std::io::{stdout, IsTerminal, Write};
+use std::ops::Range;
+
+/// Markers following the line number, the same as grep's ones
+const MATCH_MARKER: u8 = b':';
+const CONTEXT_MARKER: u8 = b'-';
+/// Printed between groups of lines which are not adjacent
+pub const GROUP_SEPARATOR: &str = "--";
+
+/// ANSI colors, the same as grep's default ones
+const FILE_NAME_COLOR: &str = "\x1b[35m";
+const LINE_NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// When to highlight the output with colors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    /// Only when printing to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub const VALUES: [&'static str; 3] = ["auto", "always", "never"];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Tells if the standard output should be colored
+    pub fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Auto => stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// What gets printed about every input
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Report {
+    /// The matching lines, with their context
+    #[default]
+    Lines,
+    /// Number of the matching lines
+    Count,
+    /// Name of the input, if any line matches
+    FilesWithMatches,
+    /// Name of the input, if no line matches
+    FilesWithoutMatch,
+    /// Nothing, the exit code tells if any line matched
+    Quiet,
+}
+
+impl Report {
+    /// Tells if the first matching line gives the whole report for the input
+    pub fn stops_at_first_match(self) -> bool {
+        matches!(
+            self,
+            Report::FilesWithMatches | Report::FilesWithoutMatch | Report::Quiet
+        )
+    }
+
+    /// Tells if the search of the input succeeded, which for `FilesWithoutMatch` means no line matched
+    pub fn succeeded(self, matched: bool) -> bool {
+        match self {
+            Report::FilesWithoutMatch => !matched,
+            _ => matched,
+        }
+    }
+}
+
+/// Line of the input, as it gets printed
+#[derive(Clone, Debug)]
+pub struct Line<'l> {
+    pub idx: usize,
+    /// Offset of the line's first byte in the input
+    pub offset: u64,
+    /// Bytes of the line, without the line ending, not necessarily UTF-8
+    pub text: &'l [u8],
+    /// Byte range of the field the patterns were matched against, when searching a single field of the records
+    pub field: Option<Range<usize>>,
+    /// Edits of the closest match in the line, when matching a fuzzy pattern
+    pub distance: Option<usize>,
+}
+
+/// Prints the lines the grep way: `file:line_number:line` for the matches,
+/// and `file-line_number-line` for their context.
+///
+/// The matching records searched by a single field get the field printed
+/// after them, following a tab, so `cut -f 2` picks it.
+#[derive(Clone, Debug, Default)]
+pub struct Printer {
+    /// Prefix the lines with the name of the input they come from
+    pub with_file_name: bool,
+    pub color: bool,
+    /// Print only the matched parts of the lines, each on its own line
+    pub only_matching: bool,
+    /// Print JSON Lines events instead, see `json::Event`
+    pub json: bool,
+    pub report: Report,
+}
+
+impl Printer {
+    /// Sink printing what the search finds to the output
+    pub fn sink<W: Write>(&self, output: W) -> PrinterSink<'_, W> {
+        PrinterSink {
+            printer: self,
+            output,
+        }
+    }
+
+    /// Tells if the printer needs to know where the matches are in the lines
+    pub fn needs_spans(&self) -> bool {
+        self.prints_lines() && (self.color || self.only_matching || self.json)
+    }
+
+    pub fn prints_lines(&self) -> bool {
+        self.report == Report::Lines
+    }
+
+    /// Tells if the binary inputs get reported rather than their lines printed
+    pub fn reports_binary(&self) -> bool {
+        self.prints_lines() && !self.json
+    }
+
+    /// Starts printing the lines of the input, before its first matching line
+    pub fn begin<W: Write>(&self, output: &mut W, name: &str) -> Result<()> {
+        match self.json && self.prints_lines() {
+            true => Event::Begin { path: name }.write(output),
+            false => Ok(()),
+        }
+    }
+
+    /// Tells a binary input matches, in place of its matching lines
+    pub fn binary_matches<W: Write>(&self, output: &mut W, name: &str) -> Result<()> {
+        writeln!(output, "Binary file {} matches", name)?;
+
+        Ok(())
+    }
+
+    /// Finishes printing the input, once it has been searched
+    pub fn end<W: Write>(&self, output: &mut W, name: &str, stats: &Stats) -> Result<()> {
+        let matched = stats.matched_lines > 0;
+
+        match self.report {
+            Report::Lines if self.json && matched => {
+                Event::End { path: name, stats }.write(output)?
+            }
+            Report::Lines | Report::Quiet => {}
+            Report::Count => {
+                if self.with_file_name {
+                    self.paint(output, FILE_NAME_COLOR, name.as_bytes())?;
+                    self.paint(output, SEPARATOR_COLOR, &[MATCH_MARKER])?;
+                }
+
+                writeln!(output, "{}", stats.matched_lines)?;
+            }
+            Report::FilesWithMatches | Report::FilesWithoutMatch => {
+                if self.report.succeeded(matched) {
+                    self.paint(output, FILE_NAME_COLOR, name.as_bytes())?;
+                    writeln!(output)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prints the lines changed in the input rewritten in place, the way a unified diff without context does
+    pub fn replaced<W: Write>(
+        &self,
+        output: &mut W,
+        name: &str,
+        lines: &[ReplacedLine],
+    ) -> Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        for header in ["--- ", "+++ "] {
+            output.write_all(header.as_bytes())?;
+            self.paint(output, FILE_NAME_COLOR, name.as_bytes())?;
+            writeln!(output)?;
+        }
+
+        // the replacements may hold line endings, moving the lines after them further
+        let mut removed_lines = 0;
+        let mut added_lines = 0;
+
+        for hunk in lines.chunk_by(|line, next| next.idx == line.idx + 1) {
+            let start = hunk[0].idx + 1;
+            let removed = hunk.len();
+            let added = hunk
+                .iter()
+                .map(|line| line.after.split(|&byte| byte == b'\n').count())
+                .sum();
+            let header = format!(
+                "@@ -{} +{} @@",
+                hunk_range(start, removed),
+                hunk_range(start + added_lines - removed_lines, added)
+            );
+
+            self.paint(output, SEPARATOR_COLOR, header.as_bytes())?;
+            writeln!(output)?;
+
+            for line in hunk {
+                self.paint(output, MATCH_COLOR, b"-")?;
+                output.write_all(line.before)?;
+                writeln!(output)?;
+            }
+
+            for line in hunk
+                .iter()
+                .flat_map(|line| line.after.split(|&byte| byte == b'\n'))
+            {
+                self.paint(output, LINE_NUMBER_COLOR, b"+")?;
+                output.write_all(line)?;
+                writeln!(output)?;
+            }
+
+            removed_lines += removed;
+            added_lines += added;
+        }
+
+        Ok(())
+    }
+
+    pub fn separator<W: Write>(&self, output: &mut W) -> Result<()> {
+        // the events tell the line numbers, so there is no need to separate the groups
+        if self.json || !self.prints_lines() {
+            return Ok(());
+        }
+
+        self.paint(output, SEPARATOR_COLOR, GROUP_SEPARATOR.as_bytes())?;
+        writeln!(output)?;
+
+        Ok(())
+    }
+
+    /// Prints the line which matched, with the replacements of the spans in place of them, if any
+    pub fn matched_line<W: Write>(
+        &self,
+        output: &mut W,
+        name: &str,
+        line: &Line,
+        spans: &[Range<usize>],
+        replacements: &[Vec<u8>],
+    ) -> Result<()> {
+        if !self.prints_lines() {
+            return Ok(());
+        }
+
+        if self.json {
+            return Event::matched(name, line, spans).write(output);
+        }
+
+        // even the empty matches get printed when there is something to replace them with
+        let matched_parts =
+            spans
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, span)| match replacements.get(idx) {
+                    Some(replacement) => Some((span, replacement.as_slice())),
+                    None if !span.is_empty() => Some((span, &line.text[span.clone()])),
+                    None => None,
+                });
+
+        if self.only_matching {
+            for (_, matched) in matched_parts {
+                self.prefix(output, name, line.idx, MATCH_MARKER)?;
+                self.paint(output, MATCH_COLOR, matched)?;
+                writeln!(output)?;
+            }
+
+            return Ok(());
+        }
+
+        self.prefix(output, name, line.idx, MATCH_MARKER)?;
+
+        let mut printed = 0;
+
+        for (span, matched) in matched_parts {
+            output.write_all(&line.text[printed..span.start])?;
+            self.paint(output, MATCH_COLOR, matched)?;
+            printed = span.end;
+        }
+
+        output.write_all(&line.text[printed..])?;
+
+        if let Some(field) = &line.field {
+            output.write_all(b"\t")?;
+            output.write_all(&line.text[field.clone()])?;
+        }
+
+        writeln!(output)?;
+
+        Ok(())
+    }
+
+    pub fn context_line<W: Write>(&self, output: &mut W, name: &str, line: &Line) -> Result<()> {
+        if self.json && self.prints_lines() {
+            return Event::context(name, line).write(output);
+        }
+
+        // there is no matched part in the context lines
+        if self.only_matching || !self.prints_lines() {
+            return Ok(());
+        }
+
+        self.prefix(output, name, line.idx, CONTEXT_MARKER)?;
+        output.write_all(line.text)?;
+        writeln!(output)?;
+
+        Ok(())
+    }
+
+    fn prefix<W: Write>(
+        &self,
+        output: &mut W,
+        name: &str,
+        line_idx: usize,
+        marker: u8,
+    ) -> Result<()> {
+        if self.with_file_name {
+            self.paint(output, FILE_NAME_COLOR, name.as_bytes())?;
+            self.paint(output, SEPARATOR_COLOR, &[marker])?;
+        }
+
+        self.paint(
+            output,
+            LINE_NUMBER_COLOR,
+            (line_idx + 1).to_string().as_bytes(),
+        )?;
+        self.paint(output, SEPARATOR_COLOR, &[marker])?;
+
+        Ok(())
+    }
+
+    fn paint<W: Write>(&self, output: &mut W, color: &str, text: &[u8]) -> Result<()> {
+        if self.color {
+            output.write_all(color.as_bytes())?;
+            output.write_all(text)?;
+            output.write_all(RESET_COLOR.as_bytes())?;
+        } else {
+            output.write_all(text)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lines of a hunk of a unified diff, the count left out when there is a single line
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        1 => start.to_string(),
+        _ => format!("{},{}", start, len),
+    }
+}
+
+/// Prints what the search finds with a `Printer`
+pub struct PrinterSink<'p, W> {
+    printer: &'p Printer,
+    output: W,
+}
+
+impl<'p, W: Write> Sink for PrinterSink<'p, W> {
+    fn needs_spans(&self) -> bool {
+        self.printer.needs_spans()
+    }
+
+    fn stops_at_first_match(&self) -> bool {
+        self.printer.report.stops_at_first_match()
+    }
+
+    fn wants_context(&self) -> bool {
+        self.printer.prints_lines()
+    }
+
+    fn reports_binary(&self) -> bool {
+        self.printer.reports_binary()
+    }
+
+    fn begin(&mut self, name: &str) -> Result<()> {
+        self.printer.begin(&mut self.output, name)
+    }
+
+    fn matched(
+        &mut self,
+        name: &str,
+        line: &Line,
+        spans: &[Range<usize>],
+        replacements: &[Vec<u8>],
+    ) -> Result<()> {
+        self.printer
+            .matched_line(&mut self.output, name, line, spans, replacements)
+    }
+
+    fn context(&mut self, name: &str, line: &Line) -> Result<()> {
+        self.printer.context_line(&mut self.output, name, line)
+    }
+
+    fn separator(&mut self) -> Result<()> {
+        self.printer.separator(&mut self.output)
+    }
+
+    fn binary_matches(&mut self, name: &str) -> Result<()> {
+        self.printer.binary_matches(&mut self.output, name)
+    }
+
+    fn replaced(&mut self, name: &str, lines: &[ReplacedLine]) -> Result<()> {
+        self.printer.replaced(&mut self.output, name, lines)
+    }
+
+    fn end(&mut self, name: &str, stats: &Stats) -> Result<()> {
+        self.printer.end(&mut self.output, name, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(
+        printer: &Printer,
+        text: &str,
+        spans: &[Range<usize>],
+        replacements: &[Vec<u8>],
+    ) -> String {
+        let mut output = vec![];
+        let line = Line {
+            idx: 14,
+            offset: 0,
+            text: text.as_bytes(),
+            field: None,
+            distance: None,
+        };
+
+        printer
+            .matched_line(&mut output, "quote.md", &line, spans, replacements)
+            .unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn it_highlights_every_matched_span() {
+        let printer = Printer {
+            with_file_name: true,
+            color: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            print(&printer, "in Colorado or Colorado", &[3..11, 15..23], &[]),
+            "\x1b[35mquote.md\x1b[0m\x1b[36m:\x1b[0m\x1b[32m15\x1b[0m\x1b[36m:\x1b[0m\
+in \x1b[1;31mColorado\x1b[0m or \x1b[1;31mColorado\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn it_prints_only_the_matched_parts_when_asked_to() {
+        let printer = Printer {
+            with_file_name: true,
+            only_matching: true,
+            ..Default::default()
+        };
+        let mut output = vec![];
+
+        let line = Line {
+            idx: 13,
+            offset: 0,
+            text: b"no match here",
+            field: None,
+            distance: None,
+        };
+
+        printer
+            .context_line(&mut output, "quote.md", &line)
+            .unwrap();
+
+        assert!(output.is_empty());
+        assert_eq!(
+            print(
+                &printer,
+                "in Colorado or Colorado",
+                &[3..11, 14..14, 15..23],
+                &[]
+            ),
+            "quote.md:15:Colorado\nquote.md:15:Colorado\n"
+        );
+    }
+
+    #[test]
+    fn it_prints_the_replacements_in_place_of_the_matches() {
+        let replacements = [b">".to_vec(), b"Utah".to_vec()];
+        let only_matching = Printer {
+            only_matching: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            print(
+                &Printer::default(),
+                "in Colorado",
+                &[0..0, 3..11],
+                &replacements
+            ),
+            "15:>in Utah\n"
+        );
+        assert_eq!(
+            print(&only_matching, "in Colorado", &[0..0, 3..11], &replacements),
+            "15:>\n15:Utah\n"
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use regex::bytes::Regex;
+use std::fmt;
+use std::ops::Range;
+
+/// Boolean query over patterns, such as `(disk OR fs) AND full`.
+///
+/// `NOT` binds tighter than `AND`, which binds tighter than `OR`, and the
+/// parentheses group the rest. The operators are written in upper case, so
+/// `and` is searched for like any other word. The patterns with spaces,
+/// parentheses or quotes in them go in double quotes, where `\"` stands for a
+/// quote and `\\` for a backslash.
+#[derive(Clone, Debug)]
+pub struct Query<T> {
+    /// Patterns of the query, in the order they come
+    terms: Vec<T>,
+    root: Node,
+}
+
+/// Node of the query tree, whose leaves are indexes of the terms
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Term(usize),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+}
+
+impl Node {
+    /// Tells if the query holds, given which of its terms matched
+    pub fn eval(&self, matched: &impl Fn(usize) -> bool) -> bool {
+        match self {
+            Node::Term(idx) => matched(*idx),
+            Node::Not(node) => !node.eval(matched),
+            Node::And(left, right) => left.eval(matched) && right.eval(matched),
+            Node::Or(left, right) => left.eval(matched) || right.eval(matched),
+        }
+    }
+
+    /// Marks the terms which are not negated, whose matches get highlighted
+    fn mark_positive(&self, negated: bool, positive: &mut [bool]) {
+        match self {
+            Node::Term(idx) => positive[*idx] = !negated,
+            Node::Not(node) => node.mark_positive(!negated, positive),
+            Node::And(left, right) | Node::Or(left, right) => {
+                left.mark_positive(negated, positive);
+                right.mark_positive(negated, positive);
+            }
+        }
+    }
+}
+
+impl<T> Query<T> {
+    pub fn terms(&self) -> &[T] {
+        &self.terms
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// Turns every term into another one, such as a pattern into its regex
+    pub fn try_map<U, E>(self, map: impl FnMut(T) -> Result<U, E>) -> Result<Query<U>, E> {
+        Ok(Query {
+            terms: self.terms.into_iter().map(map).collect::<Result<_, _>>()?,
+            root: self.root,
+        })
+    }
+
+    /// Tells which terms are not negated
+    pub fn positive_terms(&self) -> Vec<bool> {
+        let mut positive = vec![false; self.terms.len()];
+        self.root.mark_positive(false, &mut positive);
+
+        positive
+    }
+}
+
+impl Query<Regex> {
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.root.eval(&|idx| self.terms[idx].is_match(text))
+    }
+
+    /// Tells which of the terms match the text
+    pub fn matched_terms(&self, text: &[u8]) -> Vec<bool> {
+        self.terms.iter().map(|term| term.is_match(text)).collect()
+    }
+
+    /// Adds the byte ranges of the matches of the terms which are not negated
+    /// to the `spans`, if the query holds for the text, and tells if it does
+    pub fn find_spans(&self, text: &[u8], spans: &mut Vec<Range<usize>>) -> bool {
+        if !self.is_match(text) {
+            return false;
+        }
+
+        self.find_positive_spans(text, spans);
+
+        true
+    }
+
+    /// Adds the byte ranges of the matches of the terms which are not negated, whether the query holds or not
+    pub fn find_positive_spans(&self, text: &[u8], spans: &mut Vec<Range<usize>>) {
+        let found = spans.len();
+
+        for (term, positive) in self.terms.iter().zip(self.positive_terms()) {
+            if positive {
+                spans.extend(term.find_iter(text).map(|matched| matched.range()));
+            }
+        }
+
+        // the matches of different terms may come in any order, and overlap
+        let mut matched = spans.split_off(found);
+        matched.sort_by_key(|span| (span.start, span.end));
+
+        for span in matched {
+            let any_added = spans.len() > found;
+
+            match spans.last_mut() {
+                Some(last) if any_added && span.start < last.end => {
+                    last.end = last.end.max(span.end)
+                }
+                _ => spans.push(span),
+            }
+        }
+    }
+}
+
+/// Query which could not be parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    query: String,
+    /// Column of the offending character, counted from 1
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    /// Points to the offending column below the query
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "invalid query: {} at column {}",
+            self.message, self.column
+        )?;
+        writeln!(f, "    {}", self.query)?;
+        write!(f, "    {:>column$}", "^", column = self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Term(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Term(term) => write!(f, "pattern `{}`", term),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::End => write!(f, "end of query"),
+        }
+    }
+}
+
+/// Parses the query, with its terms as they are written
+pub fn parse(query: &str) -> Result<Query<String>, ParseError> {
+    let mut parser = Parser {
+        query,
+        tokens: tokenize(query)?,
+        position: 0,
+        terms: vec![],
+    };
+    let root = parser.or()?;
+
+    match parser.peek() {
+        (Token::End, _) => Ok(Query {
+            terms: parser.terms,
+            root,
+        }),
+        (token, column) => Err(error(
+            query,
+            column,
+            format!("expected AND, OR or end of query, found {}", token),
+        )),
+    }
+}
+
+fn error(query: &str, column: usize, message: String) -> ParseError {
+    ParseError {
+        query: query.to_owned(),
+        column,
+        message,
+    }
+}
+
+/// Splits the query into tokens, each with the column it starts at
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().enumerate().peekable();
+
+    while let Some((idx, char)) = chars.next() {
+        let column = idx + 1;
+
+        match char {
+            char if char.is_whitespace() => continue,
+            '(' => tokens.push((Token::Open, column)),
+            ')' => tokens.push((Token::Close, column)),
+            '"' => {
+                let mut term = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped @ ('"' | '\\'))) => term.push(escaped),
+                            Some((_, other)) => {
+                                term.push('\\');
+                                term.push(other);
+                            }
+                            None => {}
+                        },
+                        Some((_, other)) => term.push(other),
+                        None => {
+                            return Err(error(query, column, "unclosed quote".into()));
+                        }
+                    }
+                }
+
+                tokens.push((Token::Term(term), column));
+            }
+            _ => {
+                let mut word = char.to_string();
+
+                while let Some(&(_, next)) = chars.peek() {
+                    if next.is_whitespace() || next == '(' || next == ')' || next == '"' {
+                        break;
+                    }
+
+                    word.push(next);
+                    chars.next();
+                }
+
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Term(word),
+                };
+
+                tokens.push((token, column));
+            }
+        }
+    }
+
+    tokens.push((Token::End, query.chars().count() + 1));
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, one function per precedence level
+struct Parser<'q> {
+    query: &'q str,
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    terms: Vec<String>,
+}
+
+impl<'q> Parser<'q> {
+    fn peek(&self) -> (Token, usize) {
+        self.tokens[self.position].clone()
+    }
+
+    fn advance(&mut self) -> (Token, usize) {
+        let token = self.peek();
+
+        if token.0 != Token::End {
+            self.position += 1;
+        }
+
+        token
+    }
+
+    fn or(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.and()?;
+
+        while self.peek().0 == Token::Or {
+            self.advance();
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.not()?;
+
+        while self.peek().0 == Token::And {
+            self.advance();
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, ParseError> {
+        match self.peek().0 {
+            Token::Not => {
+                self.advance();
+
+                Ok(Node::Not(Box::new(self.not()?)))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        match self.advance() {
+            (Token::Term(term), _) => {
+                self.terms.push(term);
+
+                Ok(Node::Term(self.terms.len() - 1))
+            }
+            (Token::Open, open_column) => {
+                let node = self.or()?;
+
+                match self.advance() {
+                    (Token::Close, _) => Ok(node),
+                    (token, column) => Err(error(
+                        self.query,
+                        column,
+                        format!(
+                            "expected `)` closing the `(` at column {}, found {}",
+                            open_column, token
+                        ),
+                    )),
+                }
+            }
+            (token, column) => Err(error(
+                self.query,
+                column,
+                format!("expected a pattern, NOT or `(`, found {}", token),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(query: &str) -> Query<Regex> {
+        parse(query)
+            .unwrap()
+            .try_map(|term| Regex::new(&term))
+            .unwrap()
+    }
+
+    #[test]
+    fn it_parses_the_operators_by_precedence() {
+        let query = parse("a OR NOT b AND (c OR d)").unwrap();
+        let term = |idx| Box::new(Node::Term(idx));
+
+        assert_eq!(query.terms(), ["a", "b", "c", "d"]);
+        assert_eq!(
+            query.root(),
+            &Node::Or(
+                term(0),
+                Box::new(Node::And(
+                    Box::new(Node::Not(term(1))),
+                    Box::new(Node::Or(term(2), term(3)))
+                ))
+            )
+        );
+        assert_eq!(
+            parse(r#""disk full" AND "say \"hi\"" AND and"#)
+                .unwrap()
+                .terms(),
+            ["disk full", "say \"hi\"", "and"]
+        );
+    }
+
+    #[test]
+    fn it_points_to_the_offending_column() {
+        let column = |query| parse(query).unwrap_err().column;
+
+        assert_eq!(column("error AND AND timeout"), 11);
+        assert_eq!(column("(disk OR fs AND full"), 21);
+        assert_eq!(column("disk fs"), 6);
+        assert_eq!(column("disk )"), 6);
+        assert_eq!(column("full AND \"disk"), 10);
+        assert_eq!(column(""), 1);
+        assert_eq!(
+            parse("error AND AND timeout").unwrap_err().to_string(),
+            "\
+invalid query: expected a pattern, NOT or `(`, found AND at column 11
+    error AND AND timeout
+              ^"
+        );
+    }
+
+    #[test]
+    fn it_matches_the_lines_the_query_holds_for() {
+        let query = compile("error AND NOT timeout");
+
+        assert!(query.is_match(b"error: disk full"));
+        assert!(!query.is_match(b"error: timeout"));
+        assert!(!query.is_match(b"disk full"));
+
+        let query = compile("(disk OR fs) AND full");
+
+        assert!(query.is_match(b"fs is full"));
+        assert!(!query.is_match(b"disk is fine"));
+    }
+
+    #[test]
+    fn it_highlights_only_the_terms_not_negated() {
+        let query = compile("(disk OR isk) AND NOT timeout AND full");
+        let mut spans = vec![0..1, 20..21];
+
+        assert!(query.find_spans(b"disk is full", &mut spans));
+        assert_eq!(spans, [0..1, 20..21, 0..4, 8..12]);
+        assert!(!query.find_spans(b"disk is full, timeout", &mut spans));
+        assert_eq!(query.positive_terms(), [true, true, false, true]);
+    }
+}
@@ -0,0 +1,405 @@
+//! Searches many inputs at once, the way the `grepy` binary does.
+//!
+//! The inputs get searched in parallel, while what they print gets written in
+//! their order, as soon as the inputs before them are done. Every input can
+//! only get so far ahead of the one being written, so the memory used does
+//! not grow with what the inputs print.
+
+use crate::decompress::decompress;
+use crate::follow::Follower;
+use crate::fuzzy::RankedSink;
+use crate::index::{Index, Trigrams};
+use crate::printer::{Printer, Report};
+use crate::walk::{Input, STDIN_INPUT};
+use crate::{GrepyNeedle, SearchOptions, Searcher, Sink};
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File};
+use std::io::{self, stdin, BufReader, Write};
+use std::mem;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// How long a followed file is left to grow before looking at it again
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Bytes of output an input sends at once to be written
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Chunks of output an input can get ahead of the inputs before it, before waiting for its turn
+const REORDER_WINDOW: usize = 16;
+
+/// What the search of an input sends to be written, in order
+enum Output {
+    Chunk(Vec<u8>),
+    /// The input has been searched, and tells if it succeeded
+    Done(Result<bool>),
+}
+
+/// Searches the inputs for the needle, printing what they match with the printer
+pub struct Run<'a> {
+    pub needle: &'a GrepyNeedle<'a>,
+    pub options: &'a SearchOptions,
+    pub printer: Printer,
+    /// Search the compressed inputs decompressed
+    pub search_zip: bool,
+    /// Rewrite the files with the replacements, rather than printing their lines
+    pub in_place: bool,
+    /// Print the lines of every input ranked by their distance to the fuzzy pattern
+    pub rank: bool,
+}
+
+impl<'a> Run<'a> {
+    /// Searches the inputs in parallel, and tells if any of them succeeded the way the report wants.
+    ///
+    /// The inputs which can't be searched are given to `on_error`, in order,
+    /// the other inputs are searched all the same.
+    pub fn search<W: Write>(
+        &self,
+        inputs: Vec<Result<Input>>,
+        output: &mut W,
+        mut on_error: impl FnMut(anyhow::Error),
+    ) -> bool {
+        let found = AtomicBool::new(false);
+        let mut any_match = false;
+
+        if inputs.len() == 1 {
+            // nothing to keep in order, so the lines get written as they are found
+            let input = inputs.into_iter().next().expect("there is a single input");
+
+            match self.search_one(&mut self.searcher(&found), input, &found, output) {
+                Ok(matched) => any_match = matched,
+                Err(error) => on_error(error),
+            }
+
+            return any_match;
+        }
+
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let (senders, receivers): (Vec<_>, Vec<_>) = inputs
+            .iter()
+            .map(|_| mpsc::sync_channel(REORDER_WINDOW))
+            .unzip();
+        let queue = Mutex::new(inputs.into_iter().zip(senders));
+
+        thread::scope(|scope| {
+            for _ in 0..threads.min(receivers.len()) {
+                scope.spawn(|| {
+                    // every thread reuses its own searcher, with its buffers, for all the inputs it gets
+                    let mut searcher = self.searcher(&found);
+
+                    // the inputs are taken in order, so the one written next is always being searched
+                    while let Some((input, sender)) = next_input(&queue) {
+                        let mut chunks = ChunkWriter::new(&sender);
+                        let matched = self.search_one(&mut searcher, input, &found, &mut chunks);
+                        let flushed = chunks.flush();
+                        let matched = matched.and_then(|matched| {
+                            flushed?;
+                            Ok(matched)
+                        });
+
+                        // nothing waits for the input anymore once its output failed to be written
+                        let _ = sender.send(Output::Done(matched));
+                    }
+                });
+            }
+
+            let mut any_output = false;
+
+            for receiver in receivers {
+                // groups of context lines from different files are separated too
+                let separated = self.options.has_context() && any_output;
+                let mut wrote = false;
+
+                match write_in_turn(&receiver, &self.printer, separated, &mut wrote, output) {
+                    Ok(matched) => any_match |= matched,
+                    Err(error) => on_error(error),
+                }
+
+                any_output |= wrote;
+            }
+        });
+
+        any_match
+    }
+
+    /// Searches a single file as it grows, printing its lines as they are found, and tells if there was any match
+    pub fn follow<W: Write>(&self, paths: &[&str], output: &mut W) -> Result<bool> {
+        let mut searcher = Searcher::new(self.needle, self.options);
+
+        follow_input(paths, &mut searcher, &mut self.printer.sink(output))
+    }
+
+    fn searcher<'s>(&'s self, found: &'s AtomicBool) -> Searcher<'s> {
+        let searcher = Searcher::new(self.needle, self.options);
+
+        // the searches already going on stop too, as soon as any of them matches
+        match self.printer.report {
+            Report::Quiet => searcher.stopping_once(found),
+            _ => searcher,
+        }
+    }
+
+    /// Searches, or rewrites, a single input, and tells if it succeeded the way the report wants
+    fn search_one(
+        &self,
+        searcher: &mut Searcher,
+        input: Result<Input>,
+        found: &AtomicBool,
+        output: &mut dyn Write,
+    ) -> Result<bool> {
+        let report = self.printer.report;
+
+        // when quiet, a single match anywhere is the whole answer
+        if report == Report::Quiet && found.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+
+        let input = input?;
+        let mut sink = self.printer.sink(output);
+        let matched = match (self.in_place, self.rank) {
+            (true, _) => replace_input(&input, searcher, &mut sink)?,
+            (false, true) => search_input(
+                &input,
+                searcher,
+                self.search_zip,
+                &mut RankedSink::new(sink),
+            )?,
+            (false, false) => search_input(&input, searcher, self.search_zip, &mut sink)?,
+        };
+
+        Ok(report.succeeded(matched))
+    }
+}
+
+/// Leaves out the files the indexes of the directories searched tell can't have the trigrams.
+///
+/// The directories without an index, or with one which can't be read, get
+/// searched as a whole, the errors reading the indexes given to `on_error`.
+pub fn narrow_inputs(
+    inputs: Vec<Result<Input>>,
+    paths: &[&str],
+    required: &Trigrams,
+    mut on_error: impl FnMut(anyhow::Error),
+) -> Vec<Result<Input>> {
+    let indexes: Vec<(&str, Index)> = paths
+        .iter()
+        .filter(|path| Path::new(path).is_dir())
+        .filter_map(|dir| match Index::read(dir) {
+            Ok(index) => index.map(|index| (*dir, index)),
+            Err(error) => {
+                on_error(error);
+                None
+            }
+        })
+        .collect();
+
+    inputs
+        .into_iter()
+        .filter(|input| match input {
+            Ok(Input::File(path)) => indexes.iter().all(|(dir, index)| {
+                match (path.strip_prefix(dir), fs::metadata(path)) {
+                    (Ok(relative_path), Ok(metadata)) => {
+                        index.may_match(relative_path, &metadata, required)
+                    }
+                    _ => true,
+                }
+            }),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Searches a single file, or the standard input, and tells if there was any match
+pub fn search_input<S: Sink>(
+    input: &Input,
+    searcher: &mut Searcher,
+    search_zip: bool,
+    sink: &mut S,
+) -> Result<bool> {
+    let name = input.name();
+    let matched = match input {
+        Input::Stdin => {
+            let stdin = stdin();
+
+            decompress(stdin.lock(), None, search_zip)
+                .map_err(anyhow::Error::from)
+                .and_then(|reader| searcher.process_lines(reader, &name, sink))
+        }
+        Input::File(path) => File::open(path)
+            .and_then(|input_file| decompress(BufReader::new(input_file), Some(path), search_zip))
+            .map_err(anyhow::Error::from)
+            .and_then(|reader| searcher.process_lines(reader, &name, sink)),
+    };
+
+    matched.with_context(|| name)
+}
+
+/// Searches a single file as it grows, and tells if there was any match once it is no longer followed
+pub fn follow_input<S: Sink>(
+    paths: &[&str],
+    searcher: &mut Searcher,
+    sink: &mut S,
+) -> Result<bool> {
+    let path = match paths {
+        [path] if *path != STDIN_INPUT && !Path::new(path).is_dir() => Path::new(path),
+        _ => bail!("only a single file can be followed"),
+    };
+    let name = path.display().to_string();
+
+    Follower::open(path, FOLLOW_POLL_INTERVAL)
+        .map_err(anyhow::Error::from)
+        .and_then(|follower| searcher.process_lines(BufReader::new(follower), &name, sink))
+        .with_context(|| name)
+}
+
+/// Rewrites a single file with the replacements, and tells if there was any match
+pub fn replace_input<S: Sink>(
+    input: &Input,
+    searcher: &mut Searcher,
+    sink: &mut S,
+) -> Result<bool> {
+    match input {
+        Input::Stdin => bail!("the standard input can't be rewritten in place"),
+        Input::File(path) => searcher
+            .replace_in_place(path, &input.name(), sink)
+            .with_context(|| input.name()),
+    }
+}
+
+/// Takes the next input to search, along with where its output goes
+fn next_input<I: Iterator>(queue: &Mutex<I>) -> Option<I::Item> {
+    queue
+        .lock()
+        .expect("no thread panics while taking an input")
+        .next()
+}
+
+/// Writes the output of an input as it comes, once the inputs before it are done, and tells if it succeeded
+fn write_in_turn<W: Write>(
+    receiver: &Receiver<Output>,
+    printer: &Printer,
+    separated: bool,
+    wrote: &mut bool,
+    output: &mut W,
+) -> Result<bool> {
+    for message in receiver {
+        match message {
+            Output::Chunk(chunk) => {
+                if separated && !*wrote {
+                    printer.separator(output)?;
+                }
+
+                output.write_all(&chunk)?;
+                *wrote = true;
+            }
+            Output::Done(matched) => return matched,
+        }
+    }
+
+    bail!("the search of the input stopped before telling if it matched")
+}
+
+/// Sends what gets written to it in chunks, blocking once the input got too far ahead of the ones written
+struct ChunkWriter<'s> {
+    sender: &'s SyncSender<Output>,
+    chunk: Vec<u8>,
+}
+
+impl<'s> ChunkWriter<'s> {
+    fn new(sender: &'s SyncSender<Output>) -> Self {
+        Self {
+            sender,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+}
+
+impl<'s> Write for ChunkWriter<'s> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+
+        self.sender
+            .send(Output::Chunk(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the output is closed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk::{self, WalkOptions};
+    use regex::Regex;
+
+    #[test]
+    fn it_writes_the_output_of_the_inputs_in_order_however_long() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut expected = String::new();
+
+        // every file prints more than the window of chunks it can get ahead of the others
+        for file in 0..8 {
+            let path = dir.path().join(format!("{}.txt", file));
+            let lines = (0..REORDER_WINDOW * CHUNK_SIZE / 16)
+                .map(|line| format!("line {} of {}\n", line, file))
+                .collect::<String>();
+
+            for (number, line) in lines.lines().enumerate() {
+                expected += &format!("{}:{}:{}\n", path.display(), number + 1, line);
+            }
+
+            fs::write(path, lines).unwrap();
+        }
+
+        let needle = GrepyNeedle::PlainText("line");
+        let options = SearchOptions::default();
+        let run = Run {
+            needle: &needle,
+            options: &options,
+            printer: Printer {
+                with_file_name: true,
+                ..Default::default()
+            },
+            search_zip: false,
+            in_place: false,
+            rank: false,
+        };
+        let inputs = walk::inputs(&[dir.path().to_str().unwrap()], &WalkOptions::default());
+        let mut output = vec![];
+
+        assert!(run.search(inputs, &mut output, |error| panic!("{:#}", error)));
+        assert!(String::from_utf8(output).unwrap() == expected);
+    }
+
+    #[test]
+    fn it_names_the_input_that_failed() {
+        let search_term = Regex::new("essay").unwrap();
+        let grepy_needle = GrepyNeedle::Regex(&search_term);
+        let error = search_input(
+            &Input::File("input/missing.md".into()),
+            &mut Searcher::new(&grepy_needle, &SearchOptions::default()),
+            false,
+            &mut Printer::default().sink(vec![]),
+        )
+        .unwrap_err();
+
+        assert!(format!("{:#}", error).starts_with("input/missing.md: "));
+    }
+}
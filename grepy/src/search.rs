@@ -0,0 +1,1355 @@
+use crate::decompress::Compression;
+use crate::field::{self, FieldSelector, Unescaped};
+use crate::printer::Line;
+use crate::query::Query;
+use crate::GrepyNeedle;
+use anyhow::{bail, Context, Result};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tempfile::NamedTempFile;
+
+/// Tells which lines are matching, and which are their context
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    /// Lines to print before each matching line
+    pub before_context: usize,
+    /// Lines to print after each matching line
+    pub after_context: usize,
+    /// Print the lines not matching instead
+    pub invert: bool,
+    /// Stop reading the input after this many matching lines
+    pub max_count: Option<u64>,
+    /// Match the input as a whole, so the matches may span many lines
+    pub multiline: bool,
+    /// Print the lines of the binary inputs too, as if they were text
+    pub text: bool,
+    /// Template the matched parts get replaced with, where `$1` or `${name}` stand for the capture groups
+    pub replace: Option<Vec<u8>>,
+    /// Lines before and after every line a query is evaluated over, rather than the line alone
+    pub query_window: usize,
+    /// Field of the records the patterns are matched against, the records without it matching nothing
+    pub field: Option<FieldSelector>,
+}
+
+impl SearchOptions {
+    pub fn has_context(&self) -> bool {
+        self.before_context > 0 || self.after_context > 0
+    }
+}
+
+/// What the search went through in a single input
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub lines_searched: u64,
+    pub bytes_searched: u64,
+    pub matched_lines: u64,
+    /// Matched parts of the matched lines, counted only when the sink needs their spans
+    pub matches: u64,
+}
+
+/// Line of an input rewritten in place, as it was and as it is now, without its line ending
+#[derive(Debug, PartialEq)]
+pub struct ReplacedLine<'l> {
+    pub idx: usize,
+    pub before: &'l [u8],
+    pub after: &'l [u8],
+}
+
+/// Receives what the search finds in an input, as it goes.
+///
+/// Only `matched` is required, the other lines and events can be ignored.
+/// The search stops at the first error returned.
+pub trait Sink {
+    /// Tells if the sink needs to know where the matches are in the lines
+    fn needs_spans(&self) -> bool {
+        true
+    }
+
+    /// Tells if the first matching line is all the sink needs from an input
+    fn stops_at_first_match(&self) -> bool {
+        false
+    }
+
+    /// Tells if the sink wants the context lines, so the search goes on after the last match allowed
+    fn wants_context(&self) -> bool {
+        true
+    }
+
+    /// Tells if the binary inputs get reported with `binary_matches` rather than their matching lines
+    fn reports_binary(&self) -> bool {
+        false
+    }
+
+    /// Before the first matching line of the input
+    fn begin(&mut self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Line which matched, with the spans of the matches and their replacements, if any
+    fn matched(
+        &mut self,
+        name: &str,
+        line: &Line,
+        spans: &[Range<usize>],
+        replacements: &[Vec<u8>],
+    ) -> Result<()>;
+
+    /// Line around a matching one
+    fn context(&mut self, _name: &str, _line: &Line) -> Result<()> {
+        Ok(())
+    }
+
+    /// Between groups of lines which are not adjacent
+    fn separator(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Binary input which matches, in place of its matching lines
+    fn binary_matches(&mut self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Input rewritten in place with the replacements, with the lines they changed
+    fn replaced(&mut self, _name: &str, _lines: &[ReplacedLine]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Once the input has been searched, whether anything matched or not
+    fn end(&mut self, _name: &str, _stats: &Stats) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Line read for a query over windows of lines, with the terms of the query it matches
+struct WindowLine {
+    text: Vec<u8>,
+    terms: Vec<bool>,
+}
+
+/// Line kept in the ring buffer
+struct BufferedLine {
+    idx: usize,
+    offset: u64,
+    text: Vec<u8>,
+}
+
+impl BufferedLine {
+    fn as_line(&self) -> Line<'_> {
+        Line {
+            idx: self.idx,
+            offset: self.offset,
+            text: &self.text,
+            field: None,
+            distance: None,
+        }
+    }
+}
+
+/// Streaming search over the lines of the inputs.
+///
+/// Every input is read once, line by line, and only the lines of the before
+/// context are kept around, so the memory used does not grow with the input.
+/// The buffers are reused from line to line and from input to input.
+pub struct Searcher<'a> {
+    matcher: Matcher<'a>,
+    options: &'a SearchOptions,
+    line: Vec<u8>,
+    /// Where the matches are in the current line
+    spans: Vec<Range<usize>>,
+    /// What the matches of the current line get replaced with, if anything
+    replacements: Vec<Vec<u8>>,
+    /// Edits of the closest fuzzy match in the current line, when matching a fuzzy pattern
+    distance: Option<usize>,
+    /// Where the matches are in the matching lines, when the input is searched as a whole or by windows of lines
+    line_spans: BTreeMap<usize, Vec<Range<usize>>>,
+    /// Edits of the closest fuzzy match in the matching lines, when the input is searched as a whole
+    line_distances: BTreeMap<usize, usize>,
+    /// Ring buffer of the lines around the current one, when searched by a query over windows of lines
+    window: VecDeque<WindowLine>,
+    /// Where the current line is in the window, once there is one
+    window_current: Option<usize>,
+    /// Where the selected field is in the current line, if it has it
+    field: Option<Range<usize>>,
+    /// Column of the selected CSV field, named by the header of the input
+    csv_column: Option<usize>,
+    /// Selected field of the current line with its escapes decoded, if it has any
+    unescaped: Unescaped,
+    /// Ring buffer of the last lines read, which may become the before context
+    before: VecDeque<BufferedLine>,
+    /// Stats of the last input searched
+    stats: Stats,
+    /// Set once any of the searchers sharing it finds a match, which stops them all
+    found: Option<&'a AtomicBool>,
+}
+
+impl<'a> Searcher<'a> {
+    pub fn new(needle: &'a GrepyNeedle<'a>, options: &'a SearchOptions) -> Self {
+        Self {
+            matcher: Matcher::new(needle),
+            options,
+            line: vec![],
+            spans: vec![],
+            replacements: vec![],
+            distance: None,
+            line_spans: BTreeMap::new(),
+            line_distances: BTreeMap::new(),
+            window: VecDeque::with_capacity(2 * options.query_window + 1),
+            window_current: None,
+            field: None,
+            csv_column: None,
+            unescaped: Unescaped::default(),
+            before: VecDeque::with_capacity(options.before_context),
+            stats: Stats::default(),
+            found: None,
+        }
+    }
+
+    /// Stops searching as soon as any of the searchers sharing the flag finds a match, this one included
+    pub fn stopping_once(mut self, found: &'a AtomicBool) -> Self {
+        self.found = Some(found);
+        self
+    }
+
+    /// Stats of the last input searched
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Tells the sink the matching lines, with their context, as they are read, and tells if there was any.
+    ///
+    /// The inputs starting with a UTF-16 BOM get decoded to UTF-8 on the way,
+    /// any other input is searched byte by byte as it is.
+    pub fn process_lines<R: BufRead, S: Sink>(
+        &mut self,
+        reader: R,
+        name: &str,
+        sink: &mut S,
+    ) -> Result<bool> {
+        let mut reader = BufReader::new(decode(reader));
+
+        // the queries over windows of lines only read the lines of the window ahead
+        if !self.options.multiline || self.window_query().is_some() {
+            self.line_spans.clear();
+            self.line_distances.clear();
+
+            return self.search_lines(reader, name, sink);
+        }
+
+        // the matches may span many lines, so the input gets read as a whole first
+        let mut text = vec![];
+        let mut spans = vec![];
+
+        reader.read_to_end(&mut text)?;
+        self.matcher.distances.clear();
+        self.matcher.find_spans(&text, &mut spans);
+
+        let line_starts = line_starts(&text);
+
+        self.line_spans = spans_by_line(&text, &line_starts, &spans);
+        self.line_distances = distances_by_line(&line_starts, &spans, &self.matcher.distances);
+
+        self.search_lines(text.as_slice(), name, sink)
+    }
+
+    fn search_lines<R: BufRead, S: Sink>(
+        &mut self,
+        mut reader: R,
+        name: &str,
+        sink: &mut S,
+    ) -> Result<bool> {
+        let mut after_remaining = 0;
+        let mut last_printed: Option<usize> = None;
+        let mut offset = 0;
+
+        self.before.clear();
+        self.window.clear();
+        self.window_current = None;
+        self.stats = Stats::default();
+
+        let detects_binary = !self.options.text && sink.reports_binary();
+        // NUL bytes never show up in text, so they tell the input is binary
+        let mut binary = detects_binary && reader.fill_buf()?.contains(&0);
+
+        for line_idx in 0.. {
+            if self.is_done(sink, after_remaining) {
+                break;
+            }
+
+            self.line.clear();
+
+            let read = match self.window_query() {
+                Some(query) => self.read_window_line(&mut reader, query, line_idx)?,
+                None => reader.read_until(b'\n', &mut self.line)?,
+            };
+
+            if read == 0 {
+                break;
+            }
+
+            self.stats.lines_searched += 1;
+            self.stats.bytes_searched += read as u64;
+            trim_line_ending(&mut self.line);
+            binary = binary || (detects_binary && self.line.contains(&0));
+            self.field = self.find_field(line_idx)?;
+
+            let matched = !self.reached_max_count() && self.is_match(sink, line_idx);
+            let line = Line {
+                idx: line_idx,
+                offset,
+                text: &self.line,
+                field: self.field.clone(),
+                distance: self.distance,
+            };
+
+            offset += read as u64;
+
+            if let Some(found) = self.found.filter(|_| matched) {
+                found.store(true, Ordering::Relaxed);
+            }
+
+            if matched && binary {
+                // like grep, there is no point printing the lines of a binary input
+                self.stats.matched_lines += 1;
+                sink.binary_matches(name)?;
+                break;
+            }
+
+            if matched {
+                let group_start = self.before.front().map_or(line_idx, |line| line.idx);
+
+                if self.stats.matched_lines == 0 {
+                    sink.begin(name)?;
+                }
+
+                if self.options.has_context()
+                    && last_printed.is_some_and(|last| group_start > last + 1)
+                {
+                    sink.separator()?;
+                }
+
+                for context_line in self.before.iter() {
+                    sink.context(name, &context_line.as_line())?;
+                }
+
+                self.before.clear();
+                self.stats.matched_lines += 1;
+                self.stats.matches += self.spans.len() as u64;
+                sink.matched(name, &line, &self.spans, &self.replacements)?;
+                last_printed = Some(line_idx);
+                after_remaining = self.options.after_context;
+            } else if after_remaining > 0 {
+                sink.context(name, &line)?;
+                last_printed = Some(line_idx);
+                after_remaining -= 1;
+            } else if self.options.before_context > 0 {
+                self.remember(line_idx, line.offset);
+            }
+        }
+
+        sink.end(name, &self.stats)?;
+
+        Ok(self.stats.matched_lines > 0)
+    }
+
+    /// Tells if the rest of the input can't change what gets printed
+    fn is_done<S: Sink>(&self, sink: &S, after_remaining: usize) -> bool {
+        if sink.stops_at_first_match() && self.stats.matched_lines > 0 {
+            return true;
+        }
+
+        if self
+            .found
+            .is_some_and(|found| found.load(Ordering::Relaxed))
+        {
+            return true;
+        }
+
+        // the lines after the last match allowed still get printed as its context
+        self.reached_max_count() && (after_remaining == 0 || !sink.wants_context())
+    }
+
+    fn reached_max_count(&self) -> bool {
+        self.options
+            .max_count
+            .is_some_and(|max_count| self.stats.matched_lines >= max_count)
+    }
+
+    /// Finds the selected field in the current line, if any, the header of a CSV input telling its column
+    fn find_field(&mut self, line_idx: usize) -> Result<Option<Range<usize>>> {
+        let field = match &self.options.field {
+            None => None,
+            Some(FieldSelector::JsonPointer(pointer)) => {
+                field::find_json_field(&self.line, pointer)
+            }
+            Some(FieldSelector::CsvColumn(name)) if line_idx == 0 => {
+                let column = field::csv_column(&self.line, name)
+                    .with_context(|| format!("no column named `{}` in the CSV header", name))?;
+
+                self.csv_column = Some(column);
+
+                // the header is no record, so it never matches
+                None
+            }
+            Some(FieldSelector::CsvColumn(_)) => self
+                .csv_column
+                .and_then(|column| field::csv_fields(&self.line).into_iter().nth(column)),
+        };
+
+        Ok(field)
+    }
+
+    /// Tells if the current line is a match, finding the matched spans if the sink needs them
+    fn is_match<S: Sink>(&mut self, sink: &S, line_idx: usize) -> bool {
+        self.spans.clear();
+        self.matcher.distances.clear();
+        self.distance = None;
+
+        if self.options.multiline || self.window_query().is_some() {
+            self.distance = self.line_distances.remove(&line_idx);
+
+            let matched = match self.line_spans.remove(&line_idx) {
+                Some(spans) => {
+                    self.spans = spans;
+                    true
+                }
+                None => false,
+            };
+
+            return matched != self.options.invert;
+        }
+
+        self.replacements.clear();
+
+        let (searched, unescaped) = match (&self.options.field, &self.field) {
+            (None, _) => (0..self.line.len(), false),
+            (Some(selector), Some(field)) => (
+                field.clone(),
+                self.unescaped.decode(selector, &self.line, field.clone()),
+            ),
+            (Some(_), None) => return self.options.invert,
+        };
+        // the patterns match the value of the field, rather than how it is escaped in the line
+        let text = match unescaped {
+            true => &self.unescaped.text,
+            false => &self.line[searched.clone()],
+        };
+
+        // the lines not matching have no spans to highlight, so there are none when inverted
+        let matched = match &self.options.replace {
+            Some(template) => self.matcher.find_replacements(
+                text,
+                template,
+                &mut self.spans,
+                &mut self.replacements,
+            ),
+            None if sink.needs_spans() => self.matcher.find_spans(text, &mut self.spans),
+            None => self.matcher.is_match(text),
+        };
+
+        // the spans are the ones within the field, and get printed within the line
+        for span in &mut self.spans {
+            *span = match unescaped {
+                true => self.unescaped.raw_span(span),
+                false => span.start + searched.start..span.end + searched.start,
+            };
+        }
+
+        self.distance = self.matcher.distances.iter().min().copied();
+
+        matched != self.options.invert
+    }
+
+    /// Reads the line after the current one, along with the lines of its window, and tells how many bytes it has.
+    ///
+    /// A line matches when the query holds for its window, and when the line
+    /// itself matches any of the terms not negated, if the query has such terms.
+    /// Only the `2 * window + 1` lines around the current one are kept.
+    fn read_window_line<R: BufRead>(
+        &mut self,
+        reader: &mut R,
+        query: &Query<regex::bytes::Regex>,
+        line_idx: usize,
+    ) -> Result<usize> {
+        let window = self.options.query_window;
+        let mut current = self.window_current.map_or(0, |current| current + 1);
+        // the oldest line left the window, so its allocation is there to recycle
+        let mut recycled = match current > window {
+            true => {
+                current -= 1;
+                self.window.pop_front().map(|line| line.text)
+            }
+            false => None,
+        };
+
+        while self.window.len() <= current + window {
+            let mut text = recycled.take().unwrap_or_default();
+
+            text.clear();
+
+            if reader.read_until(b'\n', &mut text)? == 0 {
+                break;
+            }
+
+            let terms = query.matched_terms(line_content(&text));
+
+            self.window.push_back(WindowLine { text, terms });
+        }
+
+        let line = match self.window.get(current) {
+            Some(line) => line,
+            None => return Ok(0),
+        };
+        let positive = query.positive_terms();
+        let holds = query
+            .root()
+            .eval(&|term| self.window.iter().any(|line| line.terms[term]));
+        let matches_positive = positive
+            .iter()
+            .zip(&line.terms)
+            .any(|(&positive, &matched)| positive && matched);
+
+        if holds && (matches_positive || !positive.contains(&true)) {
+            let mut spans = vec![];
+
+            query.find_positive_spans(line_content(&line.text), &mut spans);
+            self.line_spans.insert(line_idx, spans);
+        }
+
+        self.window_current = Some(current);
+        self.line.extend_from_slice(&line.text);
+
+        Ok(line.text.len())
+    }
+
+    /// The query to evaluate over windows of lines, if any
+    fn window_query(&self) -> Option<&'a Query<regex::bytes::Regex>> {
+        match self.matcher.needle {
+            GrepyNeedle::Query(query) if self.options.query_window > 0 => Some(query),
+            _ => None,
+        }
+    }
+
+    /// Rewrites the file with the matched parts of its lines replaced, and tells if there was any match.
+    ///
+    /// The new content goes to a temporary file next to the original one,
+    /// which then takes its place, so the file is never left half written.
+    pub fn replace_in_place<S: Sink>(
+        &mut self,
+        path: &Path,
+        name: &str,
+        sink: &mut S,
+    ) -> Result<bool> {
+        let template = match &self.options.replace {
+            Some(template) => template,
+            None => bail!("nothing to replace the matches with"),
+        };
+        // the file a symlink points to gets rewritten, rather than the symlink replaced by a file
+        let path = &fs::canonicalize(path)?;
+        let text = fs::read(path)?;
+
+        if Compression::from_magic_bytes(&text).is_some() {
+            bail!("compressed inputs can't be rewritten in place");
+        }
+        if text.starts_with(&[0xFF, 0xFE]) || text.starts_with(&[0xFE, 0xFF]) {
+            // the text is searched decoded, so the spans don't line up with the UTF-16 bytes
+            bail!("UTF-16 inputs can't be rewritten in place");
+        }
+        if !self.options.text && text.contains(&0) {
+            bail!("binary inputs can't be rewritten in place");
+        }
+
+        let mut replaced = Vec::with_capacity(text.len());
+        // where the lines changed are in the text, and where they are in the replaced one
+        let mut changes = vec![];
+        let mut offset = 0;
+
+        self.stats = Stats::default();
+
+        for (line_idx, line) in text.split_inclusive(|&byte| byte == b'\n').enumerate() {
+            let content = line_content(line);
+            let mut copied = 0;
+            let replaced_start = replaced.len();
+
+            self.spans.clear();
+            self.replacements.clear();
+
+            let matched = self.matcher.find_replacements(
+                content,
+                template,
+                &mut self.spans,
+                &mut self.replacements,
+            );
+
+            for (span, replacement) in self.spans.iter().zip(&self.replacements) {
+                replaced.extend_from_slice(&content[copied..span.start]);
+                replaced.extend_from_slice(replacement);
+                copied = span.end;
+            }
+
+            replaced.extend_from_slice(&content[copied..]);
+
+            if replaced[replaced_start..] != *content {
+                changes.push((
+                    line_idx,
+                    offset..offset + content.len(),
+                    replaced_start..replaced.len(),
+                ));
+            }
+
+            replaced.extend_from_slice(&line[content.len()..]);
+            offset += line.len();
+            self.stats.lines_searched += 1;
+            self.stats.bytes_searched += line.len() as u64;
+
+            if matched {
+                self.stats.matched_lines += 1;
+                self.stats.matches += self.spans.len() as u64;
+            }
+        }
+
+        if replaced != text {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let mut replacement_file = NamedTempFile::new_in(dir)?;
+
+            replacement_file.write_all(&replaced)?;
+            replacement_file
+                .as_file()
+                .set_permissions(fs::metadata(path)?.permissions())?;
+            replacement_file.persist(path)?;
+        }
+
+        if self.stats.matched_lines > 0 {
+            let lines: Vec<_> = changes
+                .into_iter()
+                .map(|(idx, before, after)| ReplacedLine {
+                    idx,
+                    before: &text[before],
+                    after: &replaced[after],
+                })
+                .collect();
+
+            sink.replaced(name, &lines)?;
+        }
+
+        Ok(self.stats.matched_lines > 0)
+    }
+
+    /// Moves the current line to the ring buffer, recycling the oldest line's allocation
+    fn remember(&mut self, line_idx: usize, offset: u64) {
+        let recycled = match self.before.len() == self.options.before_context {
+            true => self.before.pop_front().map(|line| line.text),
+            false => None,
+        };
+        let text = std::mem::replace(&mut self.line, recycled.unwrap_or_default());
+
+        self.before.push_back(BufferedLine {
+            idx: line_idx,
+            offset,
+            text,
+        });
+    }
+}
+
+/// Matches the texts which are valid UTF-8 with the needle, and the rest byte by byte
+struct Matcher<'a> {
+    needle: &'a GrepyNeedle<'a>,
+    /// Same as the needle, but for bytes, built once a text is not valid UTF-8
+    bytes_regex: Option<regex::bytes::Regex>,
+    /// Edits of the fuzzy matches found, in the order of their spans
+    distances: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(needle: &'a GrepyNeedle<'a>) -> Self {
+        Self {
+            needle,
+            bytes_regex: None,
+            distances: vec![],
+        }
+    }
+
+    fn is_match(&mut self, text: &[u8]) -> bool {
+        match (std::str::from_utf8(text), self.needle) {
+            (_, GrepyNeedle::Query(query)) => query.is_match(text),
+            (Ok(text), _) => self.needle.is_match(text),
+            (Err(_), GrepyNeedle::Fuzzy(fuzzy)) => !fuzzy.find_matches_in_bytes(text).is_empty(),
+            (Err(_), _) => self.bytes_regex().is_match(text),
+        }
+    }
+
+    /// Adds the spans of the matches to the `spans`, and the edits of the fuzzy ones to the `distances`.
+    /// Tells if the text matches.
+    fn find_spans(&mut self, text: &[u8], spans: &mut Vec<Range<usize>>) -> bool {
+        let found = spans.len();
+        let fuzzy_matches = match (std::str::from_utf8(text), self.needle) {
+            (_, GrepyNeedle::Query(query)) => return query.find_spans(text, spans),
+            (Ok(text), GrepyNeedle::Fuzzy(fuzzy)) => fuzzy.find_matches(text),
+            (Err(_), GrepyNeedle::Fuzzy(fuzzy)) => fuzzy.find_matches_in_bytes(text),
+            (Ok(text), _) => return self.needle.find_spans(text, spans),
+            (Err(_), _) => {
+                spans.extend(
+                    self.bytes_regex()
+                        .find_iter(text)
+                        .map(|matched| matched.range()),
+                );
+
+                return spans.len() > found;
+            }
+        };
+
+        for matched in fuzzy_matches {
+            spans.push(matched.span);
+            self.distances.push(matched.distance);
+        }
+
+        spans.len() > found
+    }
+
+    /// Finds the spans of the matches, and what they get replaced with, the capture groups expanded in the template.
+    /// Tells if the text matches.
+    fn find_replacements(
+        &mut self,
+        text: &[u8],
+        template: &[u8],
+        spans: &mut Vec<Range<usize>>,
+        replacements: &mut Vec<Vec<u8>>,
+    ) -> bool {
+        // there are no capture groups in fuzzy patterns or queries, so the template is taken as it is
+        if let GrepyNeedle::Fuzzy(_) | GrepyNeedle::Query(_) = self.needle {
+            let matched = self.find_spans(text, spans);
+            replacements.extend(spans.iter().map(|_| template.to_vec()));
+
+            return matched;
+        }
+
+        let found = spans.len();
+
+        // the bytes regex matches UTF-8 text the same, so there is a single way to expand the captures
+        for captures in self.bytes_regex().captures_iter(text) {
+            let matched = captures.get(0).expect("group 0 is the whole match");
+            let mut replacement = vec![];
+
+            captures.expand(template, &mut replacement);
+            spans.push(matched.range());
+            replacements.push(replacement);
+        }
+
+        spans.len() > found
+    }
+
+    fn bytes_regex(&mut self) -> &regex::bytes::Regex {
+        let needle = self.needle;
+
+        self.bytes_regex.get_or_insert_with(|| {
+            let pattern = match needle {
+                GrepyNeedle::PlainText(needle) => regex::escape(needle),
+                GrepyNeedle::Regex(regex) => regex.as_str().to_owned(),
+                GrepyNeedle::Fuzzy(_) | GrepyNeedle::Query(_) => {
+                    unreachable!("fuzzy patterns and queries match bytes without a single regex")
+                }
+            };
+
+            regex::bytes::Regex::new(&pattern).expect("pattern has been compiled already")
+        })
+    }
+}
+
+/// Decodes the input to UTF-8 as it is read, if it starts with a UTF-16 BOM, and passes any other input through as it is
+pub fn decode<R: Read>(reader: R) -> impl Read {
+    DecodeReaderBytesBuilder::new()
+        .utf8_passthru(true)
+        .strip_bom(true)
+        .build(reader)
+}
+
+/// Maps the spans of the matches in the whole text to the spans in every line they touch.
+///
+/// A match from the middle of a line to the middle of another one touches
+/// both lines and every line between them.
+fn spans_by_line(
+    text: &[u8],
+    line_starts: &[usize],
+    spans: &[Range<usize>],
+) -> BTreeMap<usize, Vec<Range<usize>>> {
+    let mut spans_by_line: BTreeMap<usize, Vec<Range<usize>>> = BTreeMap::new();
+
+    for span in spans {
+        let lines = lines_touched(line_starts, span);
+
+        for (line_idx, &line_start) in line_starts
+            .iter()
+            .enumerate()
+            .take(lines.end() + 1)
+            .skip(*lines.start())
+        {
+            let mut line = text[line_start..]
+                .split(|&byte| byte == b'\n')
+                .next()
+                .unwrap_or_default();
+
+            if let Some(stripped) = line.strip_suffix(b"\r") {
+                line = stripped;
+            }
+
+            // the line endings are never printed, so the spans stop before them
+            let line_end = line_start + line.len();
+            let start = span.start.clamp(line_start, line_end);
+            let end = span.end.clamp(start, line_end);
+
+            spans_by_line
+                .entry(line_idx)
+                .or_default()
+                .push(start - line_start..end - line_start);
+        }
+    }
+
+    spans_by_line
+}
+
+/// Edits of the closest fuzzy match touching every line, given the edits of every span's match
+fn distances_by_line(
+    line_starts: &[usize],
+    spans: &[Range<usize>],
+    distances: &[usize],
+) -> BTreeMap<usize, usize> {
+    let mut distances_by_line = BTreeMap::new();
+
+    for (span, &distance) in spans.iter().zip(distances) {
+        for line_idx in lines_touched(line_starts, span) {
+            let closest = distances_by_line.entry(line_idx).or_insert(distance);
+
+            *closest = (*closest).min(distance);
+        }
+    }
+
+    distances_by_line
+}
+
+/// Offsets of the first byte of every line in the text
+fn line_starts(text: &[u8]) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            text.iter()
+                .enumerate()
+                .filter(|(_, &byte)| byte == b'\n')
+                .map(|(idx, _)| idx + 1),
+        )
+        .collect()
+}
+
+/// Indexes of the first and the last line the span touches
+fn lines_touched(line_starts: &[usize], span: &Range<usize>) -> RangeInclusive<usize> {
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
+    let start_line = line_of(span.start);
+
+    match span.is_empty() {
+        true => start_line..=start_line,
+        false => start_line..=line_of(span.end - 1),
+    }
+}
+
+/// Strips `\n` or `\r\n`, the same way `BufRead::lines` does
+fn trim_line_ending(line: &mut Vec<u8>) {
+    if line.ends_with(b"\n") {
+        line.pop();
+
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+}
+
+/// The line without its `\n` or `\r\n`, the same as `trim_line_ending` leaves
+fn line_content(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\n")
+        .map_or(line, |line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::{Printer, GROUP_SEPARATOR};
+    use crate::Grepy;
+    use regex::Regex;
+
+    fn search(needle: &GrepyNeedle, options: &SearchOptions, haystack: &str) -> String {
+        let mut output = vec![];
+
+        Searcher::new(needle, options)
+            .process_lines(
+                haystack.as_bytes(),
+                "quote.md",
+                &mut Printer::default().sink(&mut output),
+            )
+            .unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn it_reuses_the_searcher_across_inputs() {
+        let needle = GrepyNeedle::PlainText("b");
+        let options = SearchOptions {
+            before_context: 1,
+            ..Default::default()
+        };
+        let printer = Printer {
+            with_file_name: true,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&needle, &options);
+        let mut output = vec![];
+
+        searcher
+            .process_lines(
+                "a\r\nb\r\nc".as_bytes(),
+                "first",
+                &mut printer.sink(&mut output),
+            )
+            .unwrap();
+        searcher
+            .process_lines(
+                "c\nb\n".as_bytes(),
+                "second",
+                &mut printer.sink(&mut output),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "first-1-a\nfirst:2:b\nsecond-1-c\nsecond:2:b\n"
+        );
+    }
+
+    /// Keeps the lines as they are told
+    #[derive(Default)]
+    struct Lines(Vec<String>);
+
+    impl Sink for Lines {
+        fn matched(
+            &mut self,
+            name: &str,
+            line: &Line,
+            spans: &[Range<usize>],
+            _replacements: &[Vec<u8>],
+        ) -> Result<()> {
+            let text = String::from_utf8_lossy(line.text);
+            self.0.push(format!("{}:{}:{:?}", name, text, spans));
+
+            Ok(())
+        }
+
+        fn context(&mut self, name: &str, line: &Line) -> Result<()> {
+            let text = String::from_utf8_lossy(line.text);
+            self.0.push(format!("{}-{}", name, text));
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_tells_the_sink_every_line_with_the_spans_of_the_matches() {
+        let needle = GrepyNeedle::PlainText("b");
+        let options = SearchOptions {
+            after_context: 1,
+            ..Default::default()
+        };
+        let mut lines = Lines::default();
+
+        let matched = Searcher::new(&needle, &options)
+            .process_lines("a\nbob\n\0c\nd\n".as_bytes(), "abc", &mut lines)
+            .unwrap();
+
+        assert!(matched);
+        assert_eq!(lines.0, ["abc:bob:[0..1, 2..3]", "abc-\0c"]);
+    }
+
+    #[test]
+    fn it_maps_matches_to_every_line_they_touch() {
+        let text = b"ab\ncd\r\nef\n";
+        let line_starts = line_starts(text);
+        let spans = spans_by_line(text, &line_starts, &[1..5, 4..8, 10..10]);
+
+        assert_eq!(spans.keys().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        assert_eq!(spans[&0], vec![1..2]);
+        assert_eq!(spans[&1], vec![0..2, 1..2]);
+        assert_eq!(spans[&2], vec![0..1]);
+        assert_eq!(spans[&3], vec![0..0]);
+
+        let distances = distances_by_line(&line_starts, &[1..5, 4..8, 10..10], &[2, 1, 0]);
+
+        assert_eq!(
+            distances.into_iter().collect::<Vec<_>>(),
+            vec![(0, 2), (1, 1), (2, 1), (3, 0)]
+        );
+    }
+
+    #[test]
+    fn it_reports_binary_inputs_matching_unless_searching_them_as_text() {
+        let needle = GrepyNeedle::PlainText("essay");
+        let haystack = "ELF\0\u{1}\nessay\n";
+
+        assert_eq!(
+            search(&needle, &SearchOptions::default(), haystack),
+            "Binary file quote.md matches\n"
+        );
+        assert_eq!(
+            search(&needle, &SearchOptions::default(), "essay\nELF\0\nessay\n"),
+            "1:essay\nBinary file quote.md matches\n"
+        );
+
+        let options = SearchOptions {
+            text: true,
+            ..Default::default()
+        };
+
+        assert_eq!(search(&needle, &options, haystack), "2:essay\n");
+    }
+
+    #[test]
+    fn it_searches_bytes_of_text_which_is_not_utf8() {
+        let search_term = Regex::new("(?i)ESSAY").unwrap();
+        let needle = GrepyNeedle::Regex(&search_term);
+        let options = SearchOptions::default();
+        let only_matching = Printer {
+            only_matching: true,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&needle, &options);
+        let mut output = vec![];
+
+        searcher
+            .process_lines(
+                &b"caf\xe9 essay\nessai\n"[..],
+                "latin1",
+                &mut only_matching.sink(&mut output),
+            )
+            .unwrap();
+
+        assert_eq!(output, b"1:essay\n");
+
+        output.clear();
+        searcher
+            .process_lines(
+                &b"caf\xe9 essay\n"[..],
+                "latin1",
+                &mut Printer::default().sink(&mut output),
+            )
+            .unwrap();
+
+        assert_eq!(output, b"1:caf\xe9 essay\n");
+    }
+
+    #[test]
+    fn it_decodes_utf16_inputs() {
+        let needle = GrepyNeedle::PlainText("essay");
+        let encode = |text: &str, bom: [u8; 2], to_bytes: fn(u16) -> [u8; 2]| {
+            let mut bytes = bom.to_vec();
+            bytes.extend(text.encode_utf16().flat_map(to_bytes));
+            bytes
+        };
+
+        for utf16 in [
+            encode(
+                "What should\nan essay be?\n",
+                [0xff, 0xfe],
+                u16::to_le_bytes,
+            ),
+            encode(
+                "What should\nan essay be?\n",
+                [0xfe, 0xff],
+                u16::to_be_bytes,
+            ),
+        ] {
+            let mut output = vec![];
+
+            Searcher::new(&needle, &SearchOptions::default())
+                .process_lines(
+                    utf16.as_slice(),
+                    "utf16",
+                    &mut Printer::default().sink(&mut output),
+                )
+                .unwrap();
+
+            assert_eq!(String::from_utf8(output).unwrap(), "2:an essay be?\n");
+        }
+    }
+
+    #[test]
+    fn it_prints_the_lines_with_the_captures_expanded_in_the_replacements() {
+        let search_term = Regex::new(r"(?P<mountain>\w+)'s Peak|in (\w+)").unwrap();
+        let needle = GrepyNeedle::Regex(&search_term);
+        let options = SearchOptions {
+            replace: Some(b"[${mountain}$2]".to_vec()),
+            ..Default::default()
+        };
+        let haystack = "Pike's Peak is\nsomewhere in Colorado\nnowhere\n";
+
+        assert_eq!(
+            search(&needle, &options, haystack),
+            "1:[Pike] is\n2:somewhere [Colorado]\n"
+        );
+    }
+
+    #[test]
+    fn it_rewrites_files_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quote.md");
+        let search_term = Regex::new(r"(\w+)ado").unwrap();
+        let needle = GrepyNeedle::Regex(&search_term);
+        let options = SearchOptions {
+            replace: Some(b"${1}ADO".to_vec()),
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&needle, &options);
+        let mut output = vec![];
+
+        fs::write(
+            &path,
+            "in Colorado\r\nor Colorado, Colorado\nUtah\nColorado",
+        )
+        .unwrap();
+
+        assert!(searcher
+            .replace_in_place(&path, "quote.md", &mut Printer::default().sink(&mut output))
+            .unwrap());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "in ColorADO\r\nor ColorADO, ColorADO\nUtah\nColorADO"
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+--- quote.md
++++ quote.md
+@@ -1,2 +1,2 @@
+-in Colorado
+-or Colorado, Colorado
++in ColorADO
++or ColorADO, ColorADO
+@@ -4 +4 @@
+-Colorado
++ColorADO
+"
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        output = vec![];
+
+        assert!(!searcher
+            .replace_in_place(&path, "quote.md", &mut Printer::default().sink(&mut output))
+            .unwrap());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn it_refuses_to_rewrite_binary_and_utf_16_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quote.md");
+        let needle = GrepyNeedle::PlainText("Colorado");
+        let options = SearchOptions {
+            replace: Some(b"Utah".to_vec()),
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&needle, &options);
+        let mut utf_16 = vec![0xFF, 0xFE];
+
+        utf_16.extend("Colorado".encode_utf16().flat_map(u16::to_le_bytes));
+
+        for text in [&b"Colorado\0\n"[..], &utf_16] {
+            fs::write(&path, text).unwrap();
+
+            assert!(searcher
+                .replace_in_place(&path, "quote.md", &mut Printer::default().sink(&mut vec![]))
+                .is_err());
+            assert_eq!(fs::read(&path).unwrap(), text);
+        }
+
+        let options = SearchOptions {
+            text: true,
+            ..options
+        };
+
+        fs::write(&path, b"Colorado\0\n").unwrap();
+
+        assert!(Searcher::new(&needle, &options)
+            .replace_in_place(&path, "quote.md", &mut Printer::default().sink(&mut vec![]))
+            .unwrap());
+        assert_eq!(fs::read(&path).unwrap(), b"Utah\0\n");
+    }
+
+    #[test]
+    fn it_numbers_the_lines_after_the_replacements_adding_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("list.txt");
+        let needle = GrepyNeedle::PlainText(", ");
+        let options = SearchOptions {
+            replace: Some(b"\n".to_vec()),
+            ..Default::default()
+        };
+        let mut output = vec![];
+
+        fs::write(&path, "a, b\nc\nd, e\n").unwrap();
+
+        assert!(Searcher::new(&needle, &options)
+            .replace_in_place(&path, "list.txt", &mut Printer::default().sink(&mut output))
+            .unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\nc\nd\ne\n");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+--- list.txt
++++ list.txt
+@@ -1 +1,2 @@
+-a, b
++a
++b
+@@ -3 +4,2 @@
+-d, e
++d
++e
+"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_rewrites_the_file_a_symlink_points_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        let needle = GrepyNeedle::PlainText("old");
+        let options = SearchOptions {
+            replace: Some(b"new".to_vec()),
+            ..Default::default()
+        };
+
+        fs::write(&target, "old\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        assert!(Searcher::new(&needle, &options)
+            .replace_in_place(&link, "link.txt", &mut Printer::default().sink(vec![]))
+            .unwrap());
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+    }
+
+    #[test]
+    fn it_stops_once_any_searcher_sharing_the_flag_matched() {
+        let needle = GrepyNeedle::PlainText("match");
+        let options = SearchOptions::default();
+        let found = AtomicBool::new(false);
+        let haystack = "line\nmatch\n".repeat(100);
+        let mut first = Searcher::new(&needle, &options).stopping_once(&found);
+        let mut second = Searcher::new(&needle, &options).stopping_once(&found);
+
+        assert!(first
+            .process_lines(
+                haystack.as_bytes(),
+                "first",
+                &mut Printer::default().sink(vec![])
+            )
+            .unwrap());
+        assert!(found.load(Ordering::Relaxed));
+        assert_eq!(first.stats().lines_searched, 2);
+
+        assert!(!second
+            .process_lines(
+                haystack.as_bytes(),
+                "second",
+                &mut Printer::default().sink(vec![])
+            )
+            .unwrap());
+        assert_eq!(second.stats().lines_searched, 0);
+    }
+
+    #[test]
+    fn it_keeps_only_the_lines_of_the_window_in_memory() {
+        let query = crate::query::parse("disk AND full")
+            .unwrap()
+            .try_map(|term| regex::bytes::Regex::new(&term))
+            .unwrap();
+        let needle = GrepyNeedle::Query(&query);
+        let options = SearchOptions {
+            query_window: 2,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&needle, &options);
+        let haystack = "line\n".repeat(1000) + "disk\nline\nfull\nline\nline\nline\nfull\n";
+        let mut output = vec![];
+
+        searcher
+            .process_lines(
+                haystack.as_bytes(),
+                "lines",
+                &mut Printer::default().sink(&mut output),
+            )
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "1001:disk\n1003:full\n");
+        assert!(searcher.window.capacity() < 10);
+    }
+
+    #[test]
+    fn it_keeps_only_the_before_context_in_memory() {
+        let needle = GrepyNeedle::PlainText("match");
+        let options = SearchOptions {
+            before_context: 2,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&needle, &options);
+        let haystack = "line\n".repeat(1000) + "match\n";
+
+        searcher
+            .process_lines(
+                haystack.as_bytes(),
+                "lines",
+                &mut Printer::default().sink(vec![]),
+            )
+            .unwrap();
+
+        assert!(searcher.before.capacity() < 10);
+        assert_eq!(
+            search(&needle, &options, &haystack),
+            "999-line\n1000-line\n1001:match\n"
+        );
+    }
+
+    #[test]
+    fn it_prints_the_same_lines_as_grepy() {
+        let search_term = Regex::new("(?i)co[a-z]+(n|r)").unwrap();
+        let needle = GrepyNeedle::Regex(&search_term);
+        let options = SearchOptions {
+            before_context: 1,
+            after_context: 1,
+            ..Default::default()
+        };
+        let haystack = std::fs::read_to_string("input/quote.md").unwrap();
+        let mut grepy = Grepy::new();
+        let expected: Vec<usize> = grepy
+            .find_matches_extended(&needle, &haystack, 1)
+            .keys()
+            .map(|idx| idx + 1)
+            .collect();
+
+        let printed: Vec<usize> = search(&needle, &options, &haystack)
+            .lines()
+            .filter(|line| *line != GROUP_SEPARATOR)
+            .map(|line| line.split([':', '-']).next().unwrap().parse().unwrap())
+            .collect();
+
+        assert_eq!(printed, expected);
+    }
+}
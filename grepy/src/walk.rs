@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+/// Name of the input standing for the standard input
+pub const STDIN_INPUT: &str = "-";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    pub fn name(&self) -> String {
+        match self {
+            Input::Stdin => "(standard input)".into(),
+            Input::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// Tells which files found in the directories are worth searching
+#[derive(Clone, Debug, Default)]
+pub struct WalkOptions {
+    /// Search hidden files and directories too
+    pub hidden: bool,
+    /// Do not respect `.gitignore` and `.ignore` files
+    pub no_ignore: bool,
+    /// Search only the files matching any of the globs
+    pub include: Vec<String>,
+    /// Skip the files and directories matching any of the globs
+    pub exclude: Vec<String>,
+}
+
+/// Expands the paths into the list of inputs to search.
+///
+/// Directories are searched recursively, and their files come sorted by name,
+/// so the order of the inputs is always the same. Files given explicitly are
+/// searched even if they would be ignored while walking a directory.
+pub fn inputs(paths: &[&str], options: &WalkOptions) -> Vec<Result<Input>> {
+    if paths.is_empty() {
+        return vec![Ok(Input::Stdin)];
+    }
+
+    paths
+        .iter()
+        .flat_map(|path| match *path {
+            STDIN_INPUT => vec![Ok(Input::Stdin)],
+            _ if Path::new(path).is_dir() => walk_dir(path, options),
+            _ => vec![Ok(Input::File(PathBuf::from(path)))],
+        })
+        .collect()
+}
+
+fn walk_dir(dir: &str, options: &WalkOptions) -> Vec<Result<Input>> {
+    let overrides = match overrides(dir, options) {
+        Ok(overrides) => overrides,
+        Err(error) => return vec![Err(error)],
+    };
+
+    WalkBuilder::new(dir)
+        .hidden(!options.hidden)
+        .ignore(!options.no_ignore)
+        .git_ignore(!options.no_ignore)
+        .git_global(!options.no_ignore)
+        .git_exclude(!options.no_ignore)
+        .parents(!options.no_ignore)
+        // respect .gitignore files even outside of a git repository
+        .require_git(false)
+        .overrides(overrides)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry)
+                if entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file()) =>
+            {
+                Some(Ok(Input::File(entry.into_path())))
+            }
+            Ok(_) => None,
+            Err(error) => Some(Err(error).with_context(|| dir.to_owned())),
+        })
+        .collect()
+}
+
+fn overrides(dir: &str, options: &WalkOptions) -> Result<ignore::overrides::Override> {
+    let mut builder = OverrideBuilder::new(dir);
+
+    for glob in options.include.iter() {
+        builder.add(glob).with_context(|| glob.clone())?;
+    }
+
+    for glob in options.exclude.iter() {
+        builder
+            .add(&format!("!{}", glob))
+            .with_context(|| glob.clone())?;
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            ".gitignore",
+            ".hidden/secret.md",
+            "b/notes.txt",
+            "a/quote.md",
+            "a/build.log",
+            "z.md",
+        ];
+
+        for file in files.iter() {
+            let path = dir.path().join(file);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "essay\n").unwrap();
+        }
+
+        fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+
+        dir
+    }
+
+    fn names(dir: &Path, inputs: Vec<Result<Input>>) -> Vec<String> {
+        inputs
+            .into_iter()
+            .map(|input| match input.unwrap() {
+                Input::Stdin => STDIN_INPUT.into(),
+                Input::File(path) => path
+                    .strip_prefix(dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_walks_directories_in_deterministic_order_respecting_ignore_rules() {
+        let dir = tree();
+        let root = dir.path().to_str().unwrap();
+
+        assert_eq!(
+            names(dir.path(), inputs(&[root], &WalkOptions::default())),
+            vec!["a/quote.md", "b/notes.txt", "z.md"]
+        );
+
+        let options = WalkOptions {
+            hidden: true,
+            no_ignore: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            names(dir.path(), inputs(&[root], &options)),
+            vec![
+                ".gitignore",
+                ".hidden/secret.md",
+                "a/build.log",
+                "a/quote.md",
+                "b/notes.txt",
+                "z.md"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_includes_and_excludes_files_by_glob() {
+        let dir = tree();
+        let root = dir.path().to_str().unwrap();
+        let options = WalkOptions {
+            include: vec!["*.md".into()],
+            exclude: vec!["z.*".into()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            names(dir.path(), inputs(&[root], &options)),
+            vec!["a/quote.md"]
+        );
+    }
+
+    #[test]
+    fn it_keeps_explicit_files_and_stdin_as_given() {
+        assert_eq!(
+            inputs(&["z.log", STDIN_INPUT], &WalkOptions::default())
+                .into_iter()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>(),
+            vec![Input::File("z.log".into()), Input::Stdin]
+        );
+        assert_eq!(
+            inputs(&[], &WalkOptions::default()).pop().unwrap().unwrap(),
+            Input::Stdin
+        );
+    }
+}
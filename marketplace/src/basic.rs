@@ -0,0 +1,544 @@
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use basic_marketplace::{InventoryError, Provider, Quantity, Supply};
+
+use crate::escrow::{Amount, Commission, DisputeResolution, EscrowError, EscrowId, Settlement};
+use crate::market::{
+    DeliveryState, Identified, MarketAd, MarketConfig, MarketState, MarketSupply,
+    MarketTransaction, PurchaseError, StockError, SupplyState, UpdateState,
+};
+use crate::safeguards::PurchasePolicy;
+
+/// Supplies of different providers may share ids, so the provider is a part of the id
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct BasicSupplyId {
+    pub provider: String,
+    pub id: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicMarketer {
+    pub id: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicBuyer {
+    pub id: String,
+}
+
+impl Identified<String> for BasicBuyer {
+    fn id(&self) -> &String {
+        &self.id
+    }
+}
+
+/// A supply from the provider's inventory, put on the market
+#[derive(Clone, Debug)]
+pub struct Listing {
+    pub id: BasicSupplyId,
+    pub provided_by: String,
+    pub supply: Supply,
+    pub state: SupplyState,
+}
+
+impl MarketSupply<String> for Listing {
+    fn provided_by(&self) -> &String {
+        &self.provided_by
+    }
+
+    fn has_supply_available(&self) -> bool {
+        self.supply.quantity > 0
+    }
+}
+
+impl UpdateState<SupplyState> for Listing {
+    fn set_state(&mut self, state: SupplyState) {
+        self.state = state;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicAd {
+    pub marketer: String,
+    pub supply: BasicSupplyId,
+}
+
+impl MarketAd<String, BasicSupplyId> for BasicAd {
+    fn new(marketer_id: String, supply_id: BasicSupplyId) -> Self {
+        Self {
+            marketer: marketer_id,
+            supply: supply_id,
+        }
+    }
+
+    fn marketer(&self) -> &String {
+        &self.marketer
+    }
+
+    fn supply(&self) -> &BasicSupplyId {
+        &self.supply
+    }
+}
+
+#[derive(Debug)]
+pub struct Trade {
+    pub ad: BasicAd,
+    pub taker: String,
+    pub escrow: Option<EscrowId>,
+    pub delivery: DeliveryState,
+}
+
+impl MarketTransaction<String, BasicAd> for Trade {
+    fn new(buyer_id: String, ad: BasicAd) -> Self {
+        Self {
+            ad,
+            taker: buyer_id,
+            escrow: None,
+            delivery: DeliveryState::Delivered,
+        }
+    }
+
+    fn in_escrow(buyer_id: String, ad: BasicAd, escrow_id: EscrowId) -> Self {
+        Self {
+            ad,
+            taker: buyer_id,
+            escrow: Some(escrow_id),
+            delivery: DeliveryState::AwaitingShipment,
+        }
+    }
+
+    fn escrow(&self) -> Option<EscrowId> {
+        self.escrow
+    }
+}
+
+impl UpdateState<DeliveryState> for Trade {
+    fn set_state(&mut self, state: DeliveryState) {
+        self.delivery = state;
+    }
+}
+
+/// Where the funds held for a trade went, with the buyer, the provider and the marketer told by their names
+pub type BasicSettlement = Settlement<String, String, String>;
+
+/// Market trading the inventories built with the `basic_marketplace` crate.
+///
+/// The providers' inventories stay the source of truth for the stock:
+/// every item sold or restocked on the market is reflected in them right away.
+pub struct BasicMarket {
+    state: MarketState<BasicMarket>,
+}
+
+impl MarketConfig for BasicMarket {
+    type ProviderId = String;
+    type Provider = Provider;
+    type MarketerId = String;
+    type Marketer = BasicMarketer;
+    type BuyerId = String;
+    type Buyer = BasicBuyer;
+    type SupplyId = BasicSupplyId;
+    type Supply = Listing;
+    type Transaction = Trade;
+    type Advertisement = BasicAd;
+
+    fn state(&self) -> &MarketState<Self> {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut MarketState<Self> {
+        &mut self.state
+    }
+
+    fn stock(&self, supply: &BasicSupplyId) -> Option<u32> {
+        self.listing(supply)
+            .map(|listing| listing.supply.quantity.into())
+    }
+
+    fn take_stock(&mut self, supply: &BasicSupplyId, quantity: u32) -> Result<u32, StockError> {
+        let available = self.stock(supply).ok_or(StockError::SupplyNotFound)?;
+        let quantity = Quantity::try_from(quantity).map_err(|_| StockError::InsufficientStock {
+            requested: quantity,
+            available,
+        })?;
+
+        Ok(Self::withdraw(&mut self.state, supply, quantity)?.into())
+    }
+
+    fn return_stock(&mut self, supply: &BasicSupplyId, quantity: u32) -> Result<u32, StockError> {
+        let available = self.stock(supply).ok_or(StockError::SupplyNotFound)?;
+        let quantity = Quantity::try_from(quantity).map_err(|_| StockError::Overflow {
+            quantity: available,
+            added: quantity,
+        })?;
+
+        Ok(self.restock(supply, quantity)?.into())
+    }
+}
+
+impl From<InventoryError> for StockError {
+    fn from(error: InventoryError) -> Self {
+        match error {
+            InventoryError::SupplyNotFound(_) => StockError::SupplyNotFound,
+            InventoryError::Overflow {
+                quantity, added, ..
+            } => StockError::Overflow {
+                quantity: quantity.into(),
+                added,
+            },
+            InventoryError::InsufficientStock {
+                quantity,
+                requested,
+                ..
+            } => StockError::InsufficientStock {
+                requested,
+                available: quantity.into(),
+            },
+        }
+    }
+}
+
+impl BasicMarket {
+    /// Puts every supply from the providers' inventories on the market
+    pub fn load<I: IntoIterator<Item = Provider>>(providers: I) -> Self {
+        let mut state = MarketState::new();
+
+        for provider in providers {
+            for supply in provider.inventory.iter() {
+                let id = BasicSupplyId {
+                    provider: provider.name.clone(),
+                    id: supply.id.clone(),
+                };
+
+                state.add_supply(
+                    id.clone(),
+                    Listing {
+                        id,
+                        provided_by: provider.name.clone(),
+                        supply: supply.clone(),
+                        state: SupplyState::Created,
+                    },
+                );
+            }
+
+            state.add_provider(provider.name.clone(), provider);
+        }
+
+        Self { state }
+    }
+
+    pub fn add_marketer(&mut self, id: &str) {
+        self.state
+            .add_marketer(id.into(), BasicMarketer { id: id.into() });
+    }
+
+    pub fn add_buyer(&mut self, id: &str) {
+        self.state
+            .add_buyer(id.into(), BasicBuyer { id: id.into() });
+    }
+
+    pub fn listing(&self, id: &BasicSupplyId) -> Option<&Listing> {
+        self.state.supply(id)
+    }
+
+    /// Replaces the rules the purchases have to comply with, keeping the purchases made so far
+    pub fn set_purchase_policy(&mut self, policy: PurchasePolicy<String>) {
+        self.state.set_purchase_policy(policy);
+    }
+
+    /// Advertises the supply, as long as the marketer takes part in the market
+    pub fn advertise(&mut self, marketer_id: &str, id: &BasicSupplyId) -> Option<BasicAd> {
+        self.state.advertise(&marketer_id.into(), id)
+    }
+
+    /// Buys a single item of the advertised supply, taking it out of the provider's inventory
+    pub fn buy(&mut self, buyer: &BasicBuyer, ad: &BasicAd) -> Result<Trade, PurchaseError> {
+        MarketState::buy(self, buyer, ad)
+    }
+
+    /// Buys like `buy`, but holds the funds in the escrow until the delivery is settled
+    pub fn buy_in_escrow(
+        &mut self,
+        buyer: &BasicBuyer,
+        ad: &BasicAd,
+        amount: Amount,
+    ) -> Result<Trade, PurchaseError> {
+        MarketState::buy_in_escrow(self, buyer, ad, amount)
+    }
+
+    pub fn set_escrow(
+        &mut self,
+        release_timeout: Duration,
+        marketer_commission: Commission,
+    ) -> Result<(), EscrowError> {
+        self.state.set_escrow(release_timeout, marketer_commission)
+    }
+
+    pub fn ship(&mut self, trade: &mut Trade) -> Result<(), EscrowError> {
+        self.state.ship(trade)
+    }
+
+    pub fn confirm_delivery(&mut self, trade: &mut Trade) -> Result<BasicSettlement, EscrowError> {
+        self.state.confirm_delivery(trade)
+    }
+
+    pub fn open_dispute(&mut self, trade: &mut Trade) -> Result<(), EscrowError> {
+        self.state.open_dispute(trade)
+    }
+
+    pub fn resolve_dispute(
+        &mut self,
+        trade: &mut Trade,
+        resolution: DisputeResolution,
+    ) -> Result<BasicSettlement, EscrowError> {
+        self.state.resolve_dispute(trade, resolution)
+    }
+
+    pub fn settle_if_expired(
+        &mut self,
+        trade: &mut Trade,
+        now: Instant,
+    ) -> Option<BasicSettlement> {
+        self.state.settle_if_expired(trade, now)
+    }
+
+    /// Releases the funds of every shipment not settled in time, see `sync_delivery`
+    pub fn release_expired(&mut self, now: Instant) -> Vec<(EscrowId, BasicSettlement)> {
+        self.state.release_expired(now)
+    }
+
+    pub fn sync_delivery(&self, trade: &mut Trade) -> Result<(), EscrowError> {
+        self.state.sync_delivery(trade)
+    }
+
+    pub fn restock(
+        &mut self,
+        id: &BasicSupplyId,
+        quantity: Quantity,
+    ) -> Result<Quantity, InventoryError> {
+        let remaining = Self::provider_mut(&mut self.state, id)?.restock(&id.id, quantity)?;
+
+        Self::sync_listing(&mut self.state, id, remaining);
+
+        Ok(remaining)
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&Provider> {
+        self.state
+            .providers()
+            .find(|provider| provider.name == name)
+    }
+
+    /// Gives the providers back, with their inventories reflecting the trades made
+    pub fn into_providers(self) -> Vec<Provider> {
+        let mut providers: Vec<Provider> = self.state.providers().cloned().collect();
+
+        providers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        providers
+    }
+
+    fn withdraw(
+        state: &mut MarketState<Self>,
+        id: &BasicSupplyId,
+        quantity: Quantity,
+    ) -> Result<Quantity, InventoryError> {
+        let remaining = Self::provider_mut(state, id)?.withdraw(&id.id, quantity)?;
+
+        Self::sync_listing(state, id, remaining);
+
+        Ok(remaining)
+    }
+
+    fn provider_mut<'a>(
+        state: &'a mut MarketState<Self>,
+        id: &BasicSupplyId,
+    ) -> Result<&'a mut Provider, InventoryError> {
+        state
+            .provider_mut(&id.provider)
+            .ok_or_else(|| InventoryError::SupplyNotFound(id.id.clone()))
+    }
+
+    fn sync_listing(state: &mut MarketState<Self>, id: &BasicSupplyId, quantity: Quantity) {
+        if let Some(listing) = state.supply_mut(id) {
+            listing.supply.quantity = quantity;
+
+            // a listing not advertised yet stays so, no matter its stock
+            if listing.state != SupplyState::Created {
+                listing.set_state(match quantity {
+                    0 => SupplyState::Consumed,
+                    _ => SupplyState::Marketed,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safeguards::{PurchaseViolation, RateLimit};
+
+    fn market() -> (BasicMarket, BasicSupplyId, BasicBuyer) {
+        let mut provider = Provider::new("4f");
+        provider
+            .add_supply(Supply::new("4f-1", "Winter jacket", 2))
+            .unwrap();
+
+        let mut market = BasicMarket::load(vec![provider]);
+        market.add_marketer("m1");
+        market.add_buyer("b1");
+
+        let supply_id = BasicSupplyId {
+            provider: "4f".into(),
+            id: "4f-1".into(),
+        };
+
+        (market, supply_id, BasicBuyer { id: "b1".into() })
+    }
+
+    #[test]
+    fn it_syncs_stock_back_to_provider_inventory() {
+        let (mut market, supply_id, buyer) = market();
+        let ad = market.advertise("m1", &supply_id).unwrap();
+
+        assert!(market.buy(&buyer, &ad).is_ok());
+        assert!(market.buy(&buyer, &ad).is_ok());
+        assert_eq!(
+            market.buy(&buyer, &ad).unwrap_err(),
+            PurchaseError::OutOfStock
+        );
+        assert_eq!(market.restock(&supply_id, 5), Ok(5));
+
+        let providers = market.into_providers();
+
+        assert_eq!(providers[0].supply("4f-1").unwrap().quantity, 5);
+    }
+
+    #[test]
+    fn it_lets_only_the_marketers_of_the_market_advertise() {
+        let (mut market, supply_id, _) = market();
+
+        assert_eq!(market.advertise("m2", &supply_id), None);
+        assert_eq!(
+            market.listing(&supply_id).unwrap().state,
+            SupplyState::Created
+        );
+        assert!(market.advertise("m1", &supply_id).is_some());
+    }
+
+    #[test]
+    fn it_counts_only_the_purchases_taking_stock_against_the_limits() {
+        let (mut market, supply_id, buyer) = market();
+        let ad = market.advertise("m1", &supply_id).unwrap();
+
+        market.set_purchase_policy(PurchasePolicy {
+            max_items_per_supply: Some(1),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            MarketState::buy_taking_stock(&mut market, &buyer, &ad, |_, _| {
+                Err(StockError::InsufficientStock {
+                    requested: 1,
+                    available: 0,
+                })
+            })
+            .unwrap_err(),
+            PurchaseError::OutOfStock
+        );
+        assert!(market.buy(&buyer, &ad).is_ok());
+        assert_eq!(
+            market.buy(&buyer, &ad).unwrap_err(),
+            PurchaseError::Rejected(PurchaseViolation::SupplyLimitExceeded {
+                limit: 1,
+                already_purchased: 1
+            })
+        );
+        // the rejected purchase did not take the item
+        assert_eq!(market.stock(&supply_id), Some(1));
+    }
+
+    #[test]
+    fn it_keeps_counting_the_purchases_made_before_the_policy_changed() {
+        let (mut market, supply_id, buyer) = market();
+        let ad = market.advertise("m1", &supply_id).unwrap();
+
+        market.set_purchase_policy(PurchasePolicy {
+            max_items_per_supply: Some(1),
+            ..Default::default()
+        });
+
+        assert!(market.buy(&buyer, &ad).is_ok());
+
+        market.set_purchase_policy(PurchasePolicy {
+            max_items_per_supply: Some(1),
+            rate_limit: Some(RateLimit {
+                max_purchases: 10,
+                window: Duration::from_secs(60),
+            }),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            market.buy(&buyer, &ad).unwrap_err(),
+            PurchaseError::Rejected(PurchaseViolation::SupplyLimitExceeded {
+                limit: 1,
+                already_purchased: 1
+            })
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_listings_not_advertised_yet_out_of_the_market() {
+        let (mut market, supply_id, buyer) = market();
+
+        assert_eq!(market.restock(&supply_id, 1), Ok(3));
+        assert_eq!(
+            market.listing(&supply_id).unwrap().state,
+            SupplyState::Created
+        );
+
+        let ad = market.advertise("m1", &supply_id).unwrap();
+
+        for _ in 0..3 {
+            assert!(market.buy(&buyer, &ad).is_ok());
+        }
+
+        assert_eq!(
+            market.listing(&supply_id).unwrap().state,
+            SupplyState::Consumed
+        );
+        assert_eq!(market.restock(&supply_id, 1), Ok(1));
+        assert_eq!(
+            market.listing(&supply_id).unwrap().state,
+            SupplyState::Marketed
+        );
+    }
+
+    #[test]
+    fn it_tells_a_supply_missing_from_the_inventory_apart_from_an_empty_one() {
+        let (mut market, _, buyer) = market();
+        let supply_id = BasicSupplyId {
+            provider: "gone".into(),
+            id: "gone-1".into(),
+        };
+
+        market.state.add_supply(
+            supply_id.clone(),
+            Listing {
+                id: supply_id.clone(),
+                provided_by: "gone".into(),
+                supply: Supply::new("gone-1", "Lost jacket", 1),
+                state: SupplyState::Created,
+            },
+        );
+
+        let ad = market.advertise("m1", &supply_id).unwrap();
+
+        assert_eq!(
+            market.buy(&buyer, &ad).unwrap_err(),
+            PurchaseError::SupplyNotFound
+        );
+    }
+}
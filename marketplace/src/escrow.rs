@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+pub type Amount = u64;
+
+/// Marketer's cut of every release, in basis points (1/100th of a percent)
+pub type Commission = u16;
+
+const BASIS_POINTS: Commission = 10_000;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct EscrowId(u64);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscrowStatus {
+    /// Funds are held, waiting for the goods to be shipped
+    Held,
+    /// Goods are on their way, the release timeout is ticking
+    Shipped {
+        at: Instant,
+    },
+    Disputed,
+    Released,
+    Refunded,
+}
+
+#[derive(Clone, Debug)]
+pub struct EscrowEntry<B, P, M> {
+    pub buyer: B,
+    pub provider: P,
+    pub marketer: M,
+    pub amount: Amount,
+    pub status: EscrowStatus,
+}
+
+/// Where the funds went after an escrow entry got settled
+#[derive(Clone, Debug, PartialEq)]
+pub enum Settlement<B, P, M> {
+    Released {
+        provider: P,
+        provider_amount: Amount,
+        marketer: M,
+        marketer_amount: Amount,
+    },
+    Refunded {
+        buyer: B,
+        amount: Amount,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisputeResolution {
+    RefundBuyer,
+    ReleaseToProvider,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EscrowError {
+    NotFound(EscrowId),
+    /// The transaction was settled directly, without the escrow
+    NotInEscrow,
+    /// The entry is not in a state that allows the operation
+    InvalidStatus(EscrowStatus),
+    /// The commission is more than the whole amount
+    InvalidCommission(Commission),
+}
+
+impl Display for EscrowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EscrowError::NotFound(id) => write!(f, "escrow entry {:?} not found", id),
+            EscrowError::NotInEscrow => write!(f, "transaction is not held in escrow"),
+            EscrowError::InvalidStatus(status) => {
+                write!(f, "escrow entry cannot be changed while {:?}", status)
+            }
+            EscrowError::InvalidCommission(commission) => write!(
+                f,
+                "commission of {} basis points exceeds {}",
+                commission, BASIS_POINTS
+            ),
+        }
+    }
+}
+
+impl Error for EscrowError {}
+
+/// Escrow account holding buyers' funds until the delivery is settled
+#[derive(Debug)]
+pub struct Escrow<B, P, M> {
+    entries: HashMap<EscrowId, EscrowEntry<B, P, M>>,
+    next_id: u64,
+    /// How long after shipment the funds get released without the buyer's confirmation
+    release_timeout: Duration,
+    marketer_commission: Commission,
+}
+
+impl<B, P, M> Default for Escrow<B, P, M> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            next_id: 0,
+            release_timeout: Duration::from_secs(14 * 24 * 60 * 60),
+            marketer_commission: 0,
+        }
+    }
+}
+
+impl<B, P, M> Escrow<B, P, M> {
+    /// Fails when the marketer's commission is more than 10 000 basis points, i.e. the whole amount
+    pub fn new(
+        release_timeout: Duration,
+        marketer_commission: Commission,
+    ) -> Result<Self, EscrowError> {
+        if marketer_commission > BASIS_POINTS {
+            return Err(EscrowError::InvalidCommission(marketer_commission));
+        }
+
+        Ok(Self {
+            release_timeout,
+            marketer_commission,
+            ..Default::default()
+        })
+    }
+
+    /// Changes the release timeout and the commission, keeping the entries held so far
+    pub fn set_terms(
+        &mut self,
+        release_timeout: Duration,
+        marketer_commission: Commission,
+    ) -> Result<(), EscrowError> {
+        let terms = Self::new(release_timeout, marketer_commission)?;
+
+        self.release_timeout = terms.release_timeout;
+        self.marketer_commission = terms.marketer_commission;
+
+        Ok(())
+    }
+}
+
+impl<B: Clone, P: Clone, M: Clone> Escrow<B, P, M> {
+    pub fn get(&self, id: EscrowId) -> Option<&EscrowEntry<B, P, M>> {
+        self.entries.get(&id)
+    }
+
+    /// Total amount of funds not settled yet
+    pub fn held_amount(&self) -> Amount {
+        self.entries
+            .values()
+            .filter(|entry| Self::is_unsettled(entry.status))
+            .map(|entry| entry.amount)
+            .sum()
+    }
+
+    pub fn deposit(&mut self, buyer: B, provider: P, marketer: M, amount: Amount) -> EscrowId {
+        let id = EscrowId(self.next_id);
+        self.next_id += 1;
+
+        self.entries.insert(
+            id,
+            EscrowEntry {
+                buyer,
+                provider,
+                marketer,
+                amount,
+                status: EscrowStatus::Held,
+            },
+        );
+
+        id
+    }
+
+    pub fn mark_shipped(&mut self, id: EscrowId, now: Instant) -> Result<(), EscrowError> {
+        let entry = self.entry_mut(id)?;
+
+        match entry.status {
+            EscrowStatus::Held => {
+                entry.status = EscrowStatus::Shipped { at: now };
+                Ok(())
+            }
+            status => Err(EscrowError::InvalidStatus(status)),
+        }
+    }
+
+    /// The buyer got the goods, so the funds can go to the provider and the marketer
+    pub fn confirm_delivery(&mut self, id: EscrowId) -> Result<Settlement<B, P, M>, EscrowError> {
+        let status = self.entry_mut(id)?.status;
+
+        match status {
+            EscrowStatus::Held | EscrowStatus::Shipped { .. } => Ok(self.release(id)),
+            status => Err(EscrowError::InvalidStatus(status)),
+        }
+    }
+
+    pub fn open_dispute(&mut self, id: EscrowId) -> Result<(), EscrowError> {
+        let entry = self.entry_mut(id)?;
+
+        match entry.status {
+            EscrowStatus::Held | EscrowStatus::Shipped { .. } => {
+                entry.status = EscrowStatus::Disputed;
+                Ok(())
+            }
+            status => Err(EscrowError::InvalidStatus(status)),
+        }
+    }
+
+    pub fn resolve_dispute(
+        &mut self,
+        id: EscrowId,
+        resolution: DisputeResolution,
+    ) -> Result<Settlement<B, P, M>, EscrowError> {
+        let status = self.entry_mut(id)?.status;
+
+        match (status, resolution) {
+            (EscrowStatus::Disputed, DisputeResolution::RefundBuyer) => Ok(self.refund(id)),
+            (EscrowStatus::Disputed, DisputeResolution::ReleaseToProvider) => Ok(self.release(id)),
+            (status, _) => Err(EscrowError::InvalidStatus(status)),
+        }
+    }
+
+    /// Releases the funds of every shipment the buyer did not confirm nor dispute in time
+    pub fn release_expired(&mut self, now: Instant) -> Vec<(EscrowId, Settlement<B, P, M>)> {
+        let release_timeout = self.release_timeout;
+        let expired_ids: Vec<EscrowId> = self
+            .entries
+            .iter()
+            .filter_map(|(id, entry)| match entry.status {
+                EscrowStatus::Shipped { at }
+                    if now.saturating_duration_since(at) >= release_timeout =>
+                {
+                    Some(*id)
+                }
+                _ => None,
+            })
+            .collect();
+
+        expired_ids
+            .into_iter()
+            .map(|id| (id, self.release(id)))
+            .collect()
+    }
+
+    pub fn is_expired(&self, id: EscrowId, now: Instant) -> bool {
+        match self.entries.get(&id).map(|entry| entry.status) {
+            Some(EscrowStatus::Shipped { at }) => {
+                now.saturating_duration_since(at) >= self.release_timeout
+            }
+            _ => false,
+        }
+    }
+
+    fn entry_mut(&mut self, id: EscrowId) -> Result<&mut EscrowEntry<B, P, M>, EscrowError> {
+        self.entries.get_mut(&id).ok_or(EscrowError::NotFound(id))
+    }
+
+    fn release(&mut self, id: EscrowId) -> Settlement<B, P, M> {
+        let marketer_commission = self.marketer_commission as u128;
+        let entry = self.entries.get_mut(&id).expect("escrow entry must exist");
+
+        entry.status = EscrowStatus::Released;
+
+        let marketer_amount =
+            (entry.amount as u128 * marketer_commission / BASIS_POINTS as u128) as Amount;
+
+        Settlement::Released {
+            provider: entry.provider.clone(),
+            provider_amount: entry.amount - marketer_amount,
+            marketer: entry.marketer.clone(),
+            marketer_amount,
+        }
+    }
+
+    fn refund(&mut self, id: EscrowId) -> Settlement<B, P, M> {
+        let entry = self.entries.get_mut(&id).expect("escrow entry must exist");
+
+        entry.status = EscrowStatus::Refunded;
+
+        Settlement::Refunded {
+            buyer: entry.buyer.clone(),
+            amount: entry.amount,
+        }
+    }
+
+    fn is_unsettled(status: EscrowStatus) -> bool {
+        !matches!(status, EscrowStatus::Released | EscrowStatus::Refunded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escrow() -> Escrow<&'static str, &'static str, &'static str> {
+        // the marketer takes 10%
+        Escrow::new(Duration::from_secs(60), 1_000).unwrap()
+    }
+
+    #[test]
+    fn it_rejects_commission_above_the_whole_amount() {
+        let escrow: Result<Escrow<&str, &str, &str>, _> =
+            Escrow::new(Duration::from_secs(60), 10_001);
+
+        assert_eq!(escrow.err(), Some(EscrowError::InvalidCommission(10_001)));
+
+        let mut escrow = Escrow::new(Duration::from_secs(60), 10_000).unwrap();
+        let id = escrow.deposit("b1", "p1", "m1", 100);
+
+        assert_eq!(
+            escrow.confirm_delivery(id),
+            Ok(Settlement::Released {
+                provider: "p1",
+                provider_amount: 0,
+                marketer: "m1",
+                marketer_amount: 100,
+            })
+        );
+    }
+
+    #[test]
+    fn it_releases_funds_once_buyer_confirms_delivery() {
+        let mut escrow = escrow();
+        let id = escrow.deposit("b1", "p1", "m1", 250);
+
+        assert_eq!(escrow.held_amount(), 250);
+        assert_eq!(
+            escrow.confirm_delivery(id),
+            Ok(Settlement::Released {
+                provider: "p1",
+                provider_amount: 225,
+                marketer: "m1",
+                marketer_amount: 25,
+            })
+        );
+        assert_eq!(escrow.held_amount(), 0);
+        assert_eq!(
+            escrow.confirm_delivery(id),
+            Err(EscrowError::InvalidStatus(EscrowStatus::Released))
+        );
+    }
+
+    #[test]
+    fn it_releases_funds_when_timeout_passes_after_shipment() {
+        let mut escrow = escrow();
+        let shipped = escrow.deposit("b1", "p1", "m1", 100);
+        let not_shipped = escrow.deposit("b2", "p1", "m1", 100);
+        let now = Instant::now();
+
+        escrow.mark_shipped(shipped, now).unwrap();
+
+        assert!(escrow
+            .release_expired(now + Duration::from_secs(59))
+            .is_empty());
+
+        let released = escrow.release_expired(now + Duration::from_secs(60));
+
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].0, shipped);
+        assert_eq!(escrow.get(not_shipped).unwrap().status, EscrowStatus::Held);
+    }
+
+    #[test]
+    fn it_settles_disputes_according_to_resolution() {
+        let mut escrow = escrow();
+        let refunded = escrow.deposit("b1", "p1", "m1", 100);
+        let released = escrow.deposit("b1", "p1", "m1", 100);
+        let now = Instant::now();
+
+        escrow.mark_shipped(refunded, now).unwrap();
+        escrow.open_dispute(refunded).unwrap();
+        escrow.open_dispute(released).unwrap();
+
+        // disputed funds are not released automatically
+        assert!(escrow
+            .release_expired(now + Duration::from_secs(600))
+            .is_empty());
+
+        assert_eq!(
+            escrow.resolve_dispute(refunded, DisputeResolution::RefundBuyer),
+            Ok(Settlement::Refunded {
+                buyer: "b1",
+                amount: 100
+            })
+        );
+        assert!(matches!(
+            escrow.resolve_dispute(released, DisputeResolution::ReleaseToProvider),
+            Ok(Settlement::Released { .. })
+        ));
+        assert_eq!(
+            escrow.resolve_dispute(released, DisputeResolution::RefundBuyer),
+            Err(EscrowError::InvalidStatus(EscrowStatus::Released))
+        );
+    }
+}
@@ -0,0 +1,671 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::time::{Duration, Instant};
+
+use crate::market::{MarketAd, MarketConfig, PurchaseError, StockError};
+
+/// A single market taking part in the federation, i.e. a regional one
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct MarketId(pub String);
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ReservationId(u64);
+
+/// Items held for a purchase, taken out of the stock of the market the supply comes from
+#[derive(Debug)]
+struct Reservation<S> {
+    supply: S,
+    /// The market the purchase is made in
+    market: MarketId,
+    quantity: u32,
+    expires_at: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Listing<S, M> {
+    supply: S,
+    market: MarketId,
+    marketer: M,
+}
+
+/// A listing as seen across the whole federation
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListingView<'a, S, M> {
+    pub supply: &'a S,
+    /// The market the supply comes from
+    pub origin: &'a MarketId,
+    /// The market the supply is listed in
+    pub market: &'a MarketId,
+    pub marketer: &'a M,
+    pub available: u32,
+}
+
+impl<'a, S, M> ListingView<'a, S, M> {
+    pub fn is_cross_market(&self) -> bool {
+        self.origin != self.market
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FederationError {
+    UnknownMarket(MarketId),
+    UnknownSupply,
+    /// The marketer does not take part in the market
+    UnknownMarketer(MarketId),
+    UnknownReservation(ReservationId),
+    /// The reservation was not committed in time, so its items went back to the stock
+    ReservationExpired(ReservationId),
+    SupplyAlreadyRegistered,
+    AlreadyListed(MarketId),
+    NotListed(MarketId),
+    InsufficientStock {
+        requested: u32,
+        available: u32,
+    },
+    StockOverflow {
+        quantity: u32,
+        added: u32,
+    },
+    /// The market did not let the buyer make the purchase, so the reserved items went back to the stock
+    Rejected(PurchaseError),
+}
+
+impl Display for FederationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FederationError::UnknownMarket(market) => {
+                write!(f, "market {} is not part of the federation", market.0)
+            }
+            FederationError::UnknownSupply => write!(f, "supply is not registered"),
+            FederationError::UnknownMarketer(market) => {
+                write!(f, "marketer does not take part in market {}", market.0)
+            }
+            FederationError::UnknownReservation(id) => {
+                write!(f, "reservation {:?} not found", id)
+            }
+            FederationError::ReservationExpired(id) => {
+                write!(f, "reservation {:?} expired", id)
+            }
+            FederationError::SupplyAlreadyRegistered => {
+                write!(f, "supply is already registered")
+            }
+            FederationError::AlreadyListed(market) => {
+                write!(f, "supply is already listed in market {}", market.0)
+            }
+            FederationError::NotListed(market) => {
+                write!(f, "supply is not listed in market {}", market.0)
+            }
+            FederationError::InsufficientStock {
+                requested,
+                available,
+            } => write!(
+                f,
+                "requested {} items, but only {} are available",
+                requested, available
+            ),
+            FederationError::StockOverflow { quantity, added } => write!(
+                f,
+                "cannot add {} items to {} items in stock",
+                added, quantity
+            ),
+            FederationError::Rejected(error) => write!(f, "purchase rejected: {:?}", error),
+        }
+    }
+}
+
+impl Error for FederationError {}
+
+impl From<StockError> for FederationError {
+    fn from(error: StockError) -> Self {
+        match error {
+            StockError::SupplyNotFound => FederationError::UnknownSupply,
+            StockError::InsufficientStock {
+                requested,
+                available,
+            } => FederationError::InsufficientStock {
+                requested,
+                available,
+            },
+            StockError::Overflow { quantity, added } => {
+                FederationError::StockOverflow { quantity, added }
+            }
+        }
+    }
+}
+
+/// Keeps the supplies listed across several markets consistent with each other.
+///
+/// Every supply has a single stock, kept by the market it comes from, no matter
+/// how many markets it is listed in. A purchase first reserves the items, taking
+/// them out of that stock, so no purchase in any market can take them, and then
+/// either commits the reservation or cancels it, putting the items back. The
+/// reservations not committed within the reservation TTL get cancelled as well.
+pub struct Federation<T: MarketConfig> {
+    markets: HashMap<MarketId, T>,
+    /// The market every supply under the federation's control comes from
+    origins: HashMap<T::SupplyId, MarketId>,
+    listings: Vec<Listing<T::SupplyId, T::MarketerId>>,
+    reservations: HashMap<ReservationId, Reservation<T::SupplyId>>,
+    next_reservation: u64,
+    reservation_ttl: Duration,
+}
+
+impl<T: MarketConfig> Default for Federation<T> {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(15 * 60))
+    }
+}
+
+impl<T: MarketConfig> Federation<T> {
+    pub fn new(reservation_ttl: Duration) -> Self {
+        Self {
+            markets: HashMap::new(),
+            origins: HashMap::new(),
+            listings: vec![],
+            reservations: HashMap::new(),
+            next_reservation: 0,
+            reservation_ttl,
+        }
+    }
+
+    pub fn join(&mut self, id: MarketId, market: T) {
+        self.markets.insert(id, market);
+    }
+
+    pub fn market(&self, id: &MarketId) -> Option<&T> {
+        self.markets.get(id)
+    }
+
+    pub fn market_mut(&mut self, id: &MarketId) -> Option<&mut T> {
+        self.markets.get_mut(id)
+    }
+
+    /// Puts the stock the origin market keeps for the supply under the federation's control
+    pub fn register_supply(
+        &mut self,
+        origin: &MarketId,
+        supply: T::SupplyId,
+    ) -> Result<(), FederationError> {
+        let market = self
+            .markets
+            .get(origin)
+            .ok_or_else(|| FederationError::UnknownMarket(origin.clone()))?;
+
+        if market.stock(&supply).is_none() {
+            return Err(FederationError::UnknownSupply);
+        }
+
+        if self.origins.contains_key(&supply) {
+            return Err(FederationError::SupplyAlreadyRegistered);
+        }
+
+        self.origins.insert(supply, origin.clone());
+
+        Ok(())
+    }
+
+    /// Lists the supply in the market, which can be a different one than the supply comes from
+    pub fn list(
+        &mut self,
+        marketer: T::MarketerId,
+        supply: &T::SupplyId,
+        market: &MarketId,
+    ) -> Result<(), FederationError> {
+        self.ensure_market(market)?;
+
+        if !self.markets[market].state().has_marketer(&marketer) {
+            return Err(FederationError::UnknownMarketer(market.clone()));
+        }
+
+        if !self.origins.contains_key(supply) {
+            return Err(FederationError::UnknownSupply);
+        }
+
+        if self.listing(supply, market).is_some() {
+            return Err(FederationError::AlreadyListed(market.clone()));
+        }
+
+        self.listings.push(Listing {
+            supply: supply.clone(),
+            market: market.clone(),
+            marketer,
+        });
+
+        Ok(())
+    }
+
+    pub fn unlist(
+        &mut self,
+        supply: &T::SupplyId,
+        market: &MarketId,
+    ) -> Result<(), FederationError> {
+        let listings_count = self.listings.len();
+
+        self.listings
+            .retain(|listing| !(&listing.supply == supply && &listing.market == market));
+
+        match self.listings.len() == listings_count {
+            true => Err(FederationError::NotListed(market.clone())),
+            false => Ok(()),
+        }
+    }
+
+    /// Items of the supply left in the stock of the market it comes from
+    pub fn available(&self, supply: &T::SupplyId) -> Option<u32> {
+        self.origin_market(supply)?.stock(supply)
+    }
+
+    pub fn restock(&mut self, supply: &T::SupplyId, quantity: u32) -> Result<u32, FederationError> {
+        Ok(self
+            .origin_market_mut(supply)?
+            .return_stock(supply, quantity)?)
+    }
+
+    /// Holds the items for a purchase made in the market, so no other market can sell them.
+    ///
+    /// The items are taken out of the stock right away, and put back unless the
+    /// reservation gets committed within the reservation TTL from `now`.
+    pub fn reserve(
+        &mut self,
+        supply: &T::SupplyId,
+        market: &MarketId,
+        quantity: u32,
+        now: Instant,
+    ) -> Result<ReservationId, FederationError> {
+        if self.listing(supply, market).is_none() {
+            return Err(FederationError::NotListed(market.clone()));
+        }
+
+        self.origin_market_mut(supply)?
+            .take_stock(supply, quantity)?;
+
+        let id = ReservationId(self.next_reservation);
+        self.next_reservation += 1;
+
+        self.reservations.insert(
+            id,
+            Reservation {
+                supply: supply.clone(),
+                market: market.clone(),
+                quantity,
+                expires_at: now + self.reservation_ttl,
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Finalizes the purchase: the reserved items leave the stock for good.
+    ///
+    /// The purchase is made in the market the items were reserved in, so it is
+    /// up to that market to accept the buyer, and to check its safeguards.
+    pub fn commit(
+        &mut self,
+        reservation: ReservationId,
+        buyer: &T::Buyer,
+        now: Instant,
+    ) -> Result<T::Transaction, FederationError> {
+        let (supply, market, quantity) = match self.reservations.get(&reservation) {
+            None => return Err(FederationError::UnknownReservation(reservation)),
+            Some(value) if value.expires_at <= now => {
+                self.cancel(reservation)?;
+
+                return Err(FederationError::ReservationExpired(reservation));
+            }
+            Some(value) => (value.supply.clone(), value.market.clone(), value.quantity),
+        };
+
+        let marketer = match self.listing(&supply, &market) {
+            None => {
+                self.cancel(reservation)?;
+
+                return Err(FederationError::NotListed(market));
+            }
+            Some(listing) => listing.marketer.clone(),
+        };
+
+        let ad = T::Advertisement::new(marketer, supply);
+        let purchase = self
+            .markets
+            .get_mut(&market)
+            .ok_or_else(|| FederationError::UnknownMarket(market.clone()))?
+            .state_mut()
+            .buy_reserved(buyer, &ad, quantity);
+
+        match purchase {
+            Ok(transaction) => {
+                self.reservations.remove(&reservation);
+
+                Ok(transaction)
+            }
+            Err(error) => {
+                self.cancel(reservation)?;
+
+                Err(FederationError::Rejected(error))
+            }
+        }
+    }
+
+    /// Gives the reserved items back to the stock
+    pub fn cancel(&mut self, reservation: ReservationId) -> Result<u32, FederationError> {
+        let reservation = self
+            .reservations
+            .remove(&reservation)
+            .ok_or(FederationError::UnknownReservation(reservation))?;
+
+        self.origin_market_mut(&reservation.supply)?
+            .return_stock(&reservation.supply, reservation.quantity)?;
+
+        Ok(reservation.quantity)
+    }
+
+    /// Cancels every reservation not committed in time, giving the items back to the stock
+    pub fn cancel_expired(&mut self, now: Instant) -> Vec<(ReservationId, u32)> {
+        let expired_ids: Vec<ReservationId> = self
+            .reservations
+            .iter()
+            .filter(|(_, reservation)| reservation.expires_at <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired_ids
+            .into_iter()
+            .filter_map(|id| Some((id, self.cancel(id).ok()?)))
+            .collect()
+    }
+
+    /// Single view over the listings of all markets in the federation
+    pub fn listings(&self) -> Vec<ListingView<'_, T::SupplyId, T::MarketerId>> {
+        self.listings
+            .iter()
+            .filter_map(|listing| self.view(listing))
+            .collect()
+    }
+
+    pub fn listings_in(
+        &self,
+        market: &MarketId,
+    ) -> Vec<ListingView<'_, T::SupplyId, T::MarketerId>> {
+        self.listings
+            .iter()
+            .filter(|listing| &listing.market == market)
+            .filter_map(|listing| self.view(listing))
+            .collect()
+    }
+
+    fn view<'a>(
+        &'a self,
+        listing: &'a Listing<T::SupplyId, T::MarketerId>,
+    ) -> Option<ListingView<'a, T::SupplyId, T::MarketerId>> {
+        Some(ListingView {
+            supply: &listing.supply,
+            origin: self.origins.get(&listing.supply)?,
+            market: &listing.market,
+            marketer: &listing.marketer,
+            available: self.available(&listing.supply)?,
+        })
+    }
+
+    fn listing(
+        &self,
+        supply: &T::SupplyId,
+        market: &MarketId,
+    ) -> Option<&Listing<T::SupplyId, T::MarketerId>> {
+        self.listings
+            .iter()
+            .find(|listing| &listing.supply == supply && &listing.market == market)
+    }
+
+    fn origin_market(&self, supply: &T::SupplyId) -> Option<&T> {
+        self.markets.get(self.origins.get(supply)?)
+    }
+
+    fn origin_market_mut(&mut self, supply: &T::SupplyId) -> Result<&mut T, FederationError> {
+        let origin = self
+            .origins
+            .get(supply)
+            .ok_or(FederationError::UnknownSupply)?;
+
+        self.markets
+            .get_mut(origin)
+            .ok_or_else(|| FederationError::UnknownMarket(origin.clone()))
+    }
+
+    fn ensure_market(&self, market: &MarketId) -> Result<(), FederationError> {
+        match self.markets.contains_key(market) {
+            true => Ok(()),
+            false => Err(FederationError::UnknownMarket(market.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safeguards::{PurchasePolicy, PurchaseViolation};
+    use crate::test_market::{
+        Buyer, BuyerId, Marketer, MarketerId, MyTestMarket, Provider, SupplyId,
+    };
+
+    fn amber() -> SupplyId {
+        SupplyId("amber".into())
+    }
+
+    fn marketer(id: &str) -> MarketerId {
+        MarketerId(id.into())
+    }
+
+    fn buyer() -> Buyer {
+        Buyer::new("mr buyer".into())
+    }
+
+    /// Market with a single marketer and a single buyer taking part in it
+    fn market(marketer_id: &str) -> MyTestMarket {
+        let mut market = MyTestMarket::default();
+        let buyer = buyer();
+
+        market.state.add_marketer(
+            marketer(marketer_id),
+            Marketer {
+                id: marketer(marketer_id),
+                name: "Marketer name".into(),
+            },
+        );
+        market.state.add_buyer(buyer.id.clone(), buyer);
+
+        market
+    }
+
+    fn federation() -> (Federation<MyTestMarket>, MarketId, MarketId) {
+        let mut origin = market("m1");
+        origin
+            .state
+            .add_supply(amber(), Provider::new().creates_supply("Amber".into(), 5));
+
+        let mut federation = Federation::new(Duration::from_secs(60));
+        let north = MarketId("north".into());
+        let south = MarketId("south".into());
+
+        federation.join(north.clone(), origin);
+        federation.join(south.clone(), market("m2"));
+        federation.register_supply(&north, amber()).unwrap();
+        federation.list(marketer("m1"), &amber(), &north).unwrap();
+        federation.list(marketer("m2"), &amber(), &south).unwrap();
+
+        (federation, north, south)
+    }
+
+    fn stock_in_origin_market(federation: &Federation<MyTestMarket>, market: &MarketId) -> u32 {
+        let supply = federation.market(market).unwrap().state.supply(&amber());
+
+        supply.unwrap().available_items
+    }
+
+    #[test]
+    fn it_exposes_listings_of_all_markets() {
+        let (federation, north, south) = federation();
+        let listings = federation.listings();
+
+        assert_eq!(listings.len(), 2);
+        assert_eq!(listings[0].market, &north);
+        assert!(!listings[0].is_cross_market());
+        assert_eq!(listings[1].market, &south);
+        assert_eq!(listings[1].origin, &north);
+        assert!(listings[1].is_cross_market());
+        assert_eq!(federation.listings_in(&south).len(), 1);
+    }
+
+    #[test]
+    fn it_shares_stock_between_markets() {
+        let (mut federation, north, south) = federation();
+        let now = Instant::now();
+
+        let north_reservation = federation.reserve(&amber(), &north, 3, now).unwrap();
+
+        // the reserved items are not available in any market anymore
+        assert_eq!(federation.available(&amber()), Some(2));
+        assert_eq!(stock_in_origin_market(&federation, &north), 2);
+        assert!(federation
+            .listings()
+            .iter()
+            .all(|listing| listing.available == 2));
+        assert_eq!(
+            federation.reserve(&amber(), &south, 3, now),
+            Err(FederationError::InsufficientStock {
+                requested: 3,
+                available: 2
+            })
+        );
+
+        let south_reservation = federation.reserve(&amber(), &south, 2, now).unwrap();
+
+        let transaction = federation.commit(north_reservation, &buyer(), now).unwrap();
+
+        assert_eq!(transaction.taker, BuyerId("b1".into()));
+        assert_eq!(transaction.ad.marketer, marketer("m1"));
+        assert_eq!(federation.cancel(south_reservation), Ok(2));
+        assert_eq!(federation.available(&amber()), Some(2));
+        assert_eq!(stock_in_origin_market(&federation, &north), 2);
+        assert_eq!(
+            federation
+                .commit(north_reservation, &buyer(), now)
+                .unwrap_err(),
+            FederationError::UnknownReservation(north_reservation)
+        );
+        assert_eq!(federation.restock(&amber(), 4), Ok(6));
+        assert_eq!(stock_in_origin_market(&federation, &north), 6);
+    }
+
+    #[test]
+    fn it_gives_back_the_items_of_expired_reservations() {
+        let (mut federation, north, south) = federation();
+        let now = Instant::now();
+
+        let swept = federation.reserve(&amber(), &north, 2, now).unwrap();
+        let late = federation
+            .reserve(&amber(), &south, 2, now + Duration::from_secs(30))
+            .unwrap();
+
+        assert_eq!(federation.available(&amber()), Some(1));
+        assert!(federation
+            .cancel_expired(now + Duration::from_secs(59))
+            .is_empty());
+        assert_eq!(
+            federation.cancel_expired(now + Duration::from_secs(60)),
+            [(swept, 2)]
+        );
+        assert_eq!(federation.available(&amber()), Some(3));
+        assert_eq!(
+            federation
+                .commit(late, &buyer(), now + Duration::from_secs(90))
+                .unwrap_err(),
+            FederationError::ReservationExpired(late)
+        );
+        assert_eq!(federation.available(&amber()), Some(5));
+        assert_eq!(stock_in_origin_market(&federation, &north), 5);
+    }
+
+    #[test]
+    fn it_allows_purchases_only_where_supply_is_listed() {
+        let (mut federation, north, _) = federation();
+        let east = MarketId("east".into());
+        let now = Instant::now();
+
+        assert_eq!(
+            federation.list(marketer("m3"), &amber(), &east),
+            Err(FederationError::UnknownMarket(east.clone()))
+        );
+
+        federation.join(east.clone(), market("m3"));
+
+        assert_eq!(
+            federation.register_supply(&east, amber()),
+            Err(FederationError::UnknownSupply)
+        );
+        assert_eq!(
+            federation.register_supply(&north, amber()),
+            Err(FederationError::SupplyAlreadyRegistered)
+        );
+        assert_eq!(
+            federation.reserve(&amber(), &east, 1, now),
+            Err(FederationError::NotListed(east.clone()))
+        );
+        assert_eq!(
+            federation.list(marketer("m3"), &amber(), &north),
+            Err(FederationError::UnknownMarketer(north.clone()))
+        );
+        assert_eq!(
+            federation.list(marketer("m1"), &amber(), &north),
+            Err(FederationError::AlreadyListed(north.clone()))
+        );
+        assert_eq!(federation.unlist(&amber(), &north), Ok(()));
+        assert_eq!(
+            federation.reserve(&amber(), &north, 1, now),
+            Err(FederationError::NotListed(north))
+        );
+    }
+
+    #[test]
+    fn it_leaves_the_purchase_up_to_the_market_the_items_were_reserved_in() {
+        let (mut federation, north, south) = federation();
+        let now = Instant::now();
+        let stranger = Buyer {
+            id: BuyerId("b2".into()),
+            name: "stranger".into(),
+        };
+
+        federation
+            .market_mut(&south)
+            .unwrap()
+            .state
+            .set_purchase_policy(PurchasePolicy {
+                blocklist: vec![BuyerId("b1".into())].into_iter().collect(),
+                ..Default::default()
+            });
+
+        let south_reservation = federation.reserve(&amber(), &south, 2, now).unwrap();
+
+        assert_eq!(
+            federation
+                .commit(south_reservation, &buyer(), now)
+                .unwrap_err(),
+            FederationError::Rejected(PurchaseError::Rejected(PurchaseViolation::Blocklisted))
+        );
+
+        let north_reservation = federation.reserve(&amber(), &north, 2, now).unwrap();
+
+        assert_eq!(
+            federation
+                .commit(north_reservation, &stranger, now)
+                .unwrap_err(),
+            FederationError::Rejected(PurchaseError::BuyerNotFound)
+        );
+        // the items of the rejected purchases went back to the stock
+        assert_eq!(federation.available(&amber()), Some(5));
+        assert_eq!(
+            federation.cancel(south_reservation),
+            Err(FederationError::UnknownReservation(south_reservation))
+        );
+    }
+}
@@ -1,162 +1,18 @@
-use std::collections::HashMap;
-use std::fmt::{Debug};
-use rand::thread_rng;
-use rand::seq::SliceRandom;
-use crate::market::{MarketConfig, MarketState, SupplyState };
-use std::borrow::BorrowMut;
-
+pub use crate::basic::{BasicAd, BasicBuyer, BasicMarket, BasicMarketer, BasicSettlement, BasicSupplyId, Listing, Trade};
+pub use crate::escrow::{Amount, Commission, DisputeResolution, Escrow, EscrowEntry, EscrowError, EscrowId, EscrowStatus, Settlement};
+pub use crate::federation::{Federation, FederationError, ListingView, MarketId, ReservationId};
+pub use crate::market::{DeliveryState, Identified, MarketAd, MarketConfig, MarketState, MarketSupply, MarketTransaction, PurchaseError, StockError, SupplyState, UpdateState};
+pub use crate::safeguards::{PurchaseGuard, PurchasePolicy, PurchaseViolation, RateLimit, VelocityLimit};
+
+mod basic;
+mod escrow;
+mod federation;
 mod market;
+mod safeguards;
+#[cfg(test)]
+mod test_market;
 
-#[derive(Default)]
-struct MyTestMarket {
-    state: MarketState<MarketConfig>,
-}
-
-impl MarketConfig for MyTestMarket {
-    type ProviderId = ProviderId;
-    type Provider = Provider;
-    type MarketerId = MarketerId;
-    type Marketer = Marketer;
-    type BuyerId = BuyerId;
-    type Buyer = Buyer;
-    type SupplyId = SupplyId;
-    type Supply = Supply;
-    type Transaction = Transaction;
-    type Advertisement = Ad;
-
-    fn state(&self) -> &MarketState<Self> {
-        &self.state
-    }
-
-    fn state_mut(&mut self) -> &mut MarketState<Self> {
-        self.state.borrow_mut()
-    }
-}
-
-#[derive(Clone,Debug,Hash,PartialEq,Eq)]
-struct ProviderId(String);
-
-#[derive(Clone)]
-struct Provider {
-    id: ProviderId,
-    name: String,
-}
-
-impl Provider {
-    fn new() -> Self {
-        Self {
-            id: ProviderId("p1".into()),
-            name: "Provider name".into(),
-        }
-    }
-
-    fn creates_supply(&self, name: String, available_items: u32) -> Supply {
-        Supply::new(self.id.clone(), name, available_items)
-    }
-}
-
-#[derive(Clone,Debug,Hash,PartialEq,Eq)]
-struct MarketerId(String);
-
-#[derive(Clone)]
-struct Marketer {
-    id: MarketerId,
-    name: String,
-}
-
-impl Marketer {
-    fn new() -> Self {
-        Marketer {
-            id: MarketerId("m1".into()),
-            name: "Marketer name".into(),
-        }
-    }
-}
-
-#[derive(Clone,Debug,Hash,PartialEq,Eq)]
-struct BuyerId(String);
-
-#[derive(Clone)]
-struct Buyer {
-    id: BuyerId,
-    name: String,
-}
-
-impl Buyer {
-    fn new(name: String) -> Self {
-        Buyer {
-            id: BuyerId("b1".into()),
-            name,
-        }
-    }
-}
-
-type AvailableSupply = u32;
-
-#[derive(Clone,Debug,Hash,PartialEq,Eq)]
-struct SupplyId(String);
-
-#[derive(Clone,Debug,Hash,PartialEq,Eq)]
-struct Supply {
-    id: SupplyId,
-    provided_by: ProviderId,
-    name: String,
-    available_items: AvailableSupply,
-    state: SupplyState,
-}
-
-impl Supply {
-    fn new(provider_id: ProviderId, name: String, available_items: AvailableSupply) -> Self {
-        Self {
-            name,
-            available_items,
-            provided_by: provider_id,
-            state: SupplyState::Created,
-            id: SupplyId("s".into()),
-        }
-    }
-
-    fn has_supply_available(&self) -> bool {
-        self.available_items > 0
-    }
-
-    fn set_state(&mut self, state: SupplyState) {
-        self.state = state;
-    }
-}
-
-
-#[derive(Clone,Debug,PartialEq)]
-struct Ad {
-    marketer: MarketerId,
-    supply: SupplyId,
-}
-
-impl Ad {
-    fn new(marketer_id: MarketerId, supply_id: SupplyId) -> Self {
-        Self {
-            marketer: marketer_id,
-            supply: supply_id,
-        }
-    }
-}
-
-#[derive(Debug)]
-struct Transaction {
-    ad: Ad,
-    taker: BuyerId,
-}
-
-impl Transaction {
-    fn new(buyer_id: BuyerId, ad: Ad) -> Self {
-        Self {
-            ad,
-            taker: buyer_id,
-        }
-    }
-}
-
-const VERSION: &'static str = "0.0.1";
+const VERSION: &str = "0.0.1";
 
 pub fn version() -> &'static str {
     VERSION
@@ -164,9 +20,11 @@ pub fn version() -> &'static str {
 
 #[cfg(test)]
 mod tests {
-    use std::borrow::{Borrow, BorrowMut};
+    use rand::thread_rng;
+    use rand::seq::SliceRandom;
 
     use super::*;
+    use crate::test_market::{Ad, Buyer, BuyerId, Marketer, MyTestMarket, Provider, SupplyId};
 
     #[test]
     fn it_works() {
@@ -178,64 +36,65 @@ mod tests {
         // let's create a place for actors to connect
         let mut market = MyTestMarket::default();
 
-        let state = market.state();
-
-        // // now, create actors that will interact with each other
-        // let provider = Provider::new();
-        // let marketer = Marketer::new();
-        // let buyer = Buyer::new("mr buyer".into());
-        //
-        // // put the actors into the place
-        // market.providers.insert(provider.id.clone(), provider);
-        // market.marketers.insert(marketer.id.clone(), marketer);
-        // market.buyers.insert(buyer.id.clone(), buyer);
-        //
-        // // everyone is ready to start
-        //
-        // // first, the provider needs to manufacture some goods/services
-        // if let Some(selected_provider) = market.providers.get(&ProviderId("p1".into())) {
-        //     // well, let's use some sea treasury
-        //     market.supplies = vec![
-        //         selected_provider.creates_supply("amber".into(), 20),
-        //         selected_provider.creates_supply("pearl".into(), 5),
-        //         selected_provider.creates_supply("sea shell".into(), 100),
-        //     ].iter().fold(
-        //         HashMap::new(),
-        //         |mut acc, supply| {
-        //             acc.insert(supply.id.clone(), supply.to_owned());
-        //             acc
-        //         }
-        //     );
-        //
-        //     // the supply is provided, so marketer can start their part of the job
-        //     let mut jewelry_ads_listing: Vec<Ad> = market.supplies.iter_mut().filter_map(|(supply_id, supply)| {
-        //         if supply.available_items > 30 {
-        //             return None;
-        //         }
-        //
-        //         market.advertise(supply)
-        //     }).collect();
-        //
-        //     // let's use some randomness!
-        //     let mut rng = thread_rng();
-        //
-        //     // once supply has been put on the market, it is now advertisment, or in short: an ad
-        //     // the ad can be bid against by a buyer, which in turn creates a transaction
-        //     // between the market maker (the marketer) and the market taker (buyer)
-        //     if let Some(buyer) = market.buyers.get(&BuyerId("b1".into())) {
-        //         if let Some(ad) = jewelry_ads_listing.choose_mut(&mut rng) {
-        //             let supply = market.supplies.get_mut(&ad.supply);
-        //
-        //             if let Some(supply) = supply {
-        //                 let transaction = market.bid(buyer, ad);
-        //                 println!("ADs from the jewelry listing: {:?}", jewelry_ads_listing);
-        //                 println!("TX: {:?}", transaction);
-        //             }
-        //         }
-        //     }
-        //
-        //     // FIXME: supply must have the statuses updated accordingly after the transaction from above
-        //     println!("All supplies available: {:?}", market.supplies);
-        // }
+        // now, create actors that will interact with each other
+        let provider = Provider::new();
+        let marketer = Marketer::new();
+        let buyer = Buyer::new("mr buyer".into());
+
+        // first, the provider needs to manufacture some goods/services
+        // well, let's use some sea treasury
+        let supplies = vec![
+            provider.creates_supply("amber".into(), 20),
+            provider.creates_supply("pearl".into(), 5),
+            provider.creates_supply("sea shell".into(), 100),
+        ];
+
+        // put the actors into the place
+        for supply in supplies {
+            market.state.add_supply(SupplyId(supply.name.clone()), supply);
+        }
+        market.state.add_provider(provider.id.clone(), provider);
+        market.state.add_marketer(marketer.id.clone(), marketer.clone());
+        market.state.add_buyer(buyer.id.clone(), buyer.clone());
+
+        // everyone is ready to start
+
+        // the supply is provided, so marketer can start their part of the job
+        let mut jewelry_ads_listing: Vec<Ad> = ["amber", "pearl", "sea shell"].iter().filter_map(|name| {
+            let supply_id = SupplyId(name.to_string());
+
+            if market.state.supply(&supply_id)?.available_items > 30 {
+                return None;
+            }
+
+            market.state.advertise(&marketer.id, &supply_id)
+        }).collect();
+
+        assert_eq!(jewelry_ads_listing.len(), 2);
+
+        // let's use some randomness!
+        let mut rng = thread_rng();
+
+        // once supply has been put on the market, it is now advertisment, or in short: an ad
+        // the ad can be bid against by a buyer, which in turn creates a transaction
+        // between the market maker (the marketer) and the market taker (buyer)
+        let ad = jewelry_ads_listing.choose_mut(&mut rng).unwrap();
+        let transaction = MarketState::buy(&mut market, &buyer, ad).unwrap();
+
+        assert_eq!(transaction.taker, BuyerId("b1".into()));
+        assert_eq!(&transaction.ad, ad);
+
+        // the item left the stock, and the rest is still on the market
+        for (name, available_items) in [("amber", 20), ("pearl", 5)] {
+            let supply = market.state.supply(&SupplyId(name.into())).unwrap();
+            let taken = match supply.name == transaction.ad.supply.0 {
+                true => 1,
+                false => 0,
+            };
+
+            assert_eq!(supply.available_items, available_items - taken);
+            assert_eq!(supply.state, SupplyState::Marketed);
+        }
+        assert_eq!(market.state.supply(&SupplyId("sea shell".into())).unwrap().state, SupplyState::Created);
     }
 }
@@ -1,78 +1,495 @@
 use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+use crate::escrow::{Amount, Commission, DisputeResolution, Escrow, EscrowError, EscrowId, EscrowStatus, Settlement};
+use crate::safeguards::{PurchaseGuard, PurchasePolicy, PurchaseViolation};
 
-pub trait MarketConfig {
-    type ProviderId;
+pub trait MarketConfig: Sized {
+    type ProviderId: Clone + Eq + Hash;
     type Provider;
-    type MarketerId;
+    type MarketerId: Clone + Eq + Hash;
     type Marketer;
-    type BuyerId;
-    type Buyer;
-    type SupplyId;
-    type Supply: UpdateState<SupplyState>;
-    type Transaction;
-    type Advertisement;
-    type State = MarketState<Self>;
+    type BuyerId: Clone + Eq + Hash;
+    type Buyer: Identified<Self::BuyerId>;
+    type SupplyId: Clone + Eq + Hash;
+    type Supply: MarketSupply<Self::ProviderId>;
+    type Transaction: MarketTransaction<Self::BuyerId, Self::Advertisement>;
+    type Advertisement: MarketAd<Self::MarketerId, Self::SupplyId>;
 
-    fn state(&self) -> MarketState<Self>;
+    fn state(&self) -> &MarketState<Self>;
 
     fn state_mut(&mut self) -> &mut MarketState<Self>;
+
+    /// Items of the supply left in stock
+    fn stock(&self, supply: &Self::SupplyId) -> Option<u32>;
+
+    /// Takes the items out of the supply's stock, and tells how many are left
+    fn take_stock(&mut self, supply: &Self::SupplyId, quantity: u32) -> Result<u32, StockError>;
+
+    /// Puts the items back into the supply's stock, and tells how many there are
+    fn return_stock(&mut self, supply: &Self::SupplyId, quantity: u32) -> Result<u32, StockError>;
 }
 
-trait UpdateState<State> {
+pub trait UpdateState<State> {
     fn set_state(&mut self, state: State);
 }
 
+/// Participant of the market, told apart from the others by its id
+pub trait Identified<Id> {
+    fn id(&self) -> &Id;
+}
+
+/// Supply a provider put on the market
+pub trait MarketSupply<ProviderId>: UpdateState<SupplyState> {
+    fn provided_by(&self) -> &ProviderId;
+
+    fn has_supply_available(&self) -> bool;
+}
+
+/// Advertisement a marketer made of a supply
+pub trait MarketAd<MarketerId, SupplyId>: Clone {
+    fn new(marketer_id: MarketerId, supply_id: SupplyId) -> Self;
+
+    fn marketer(&self) -> &MarketerId;
+
+    fn supply(&self) -> &SupplyId;
+}
+
+/// Transaction made when a buyer takes an advertisement, settled directly or through the escrow
+pub trait MarketTransaction<BuyerId, Advertisement>: UpdateState<DeliveryState> {
+    fn new(buyer_id: BuyerId, ad: Advertisement) -> Self;
+
+    fn in_escrow(buyer_id: BuyerId, ad: Advertisement, escrow_id: EscrowId) -> Self;
+
+    /// The escrow entry holding the funds, if the transaction is settled through the escrow
+    fn escrow(&self) -> Option<EscrowId>;
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum SupplyState {
     Created,
     Marketed,
     Consumed,
 }
 
+/// Delivery progress of a transaction settled through the escrow
+#[derive(Debug, PartialEq)]
+pub enum DeliveryState {
+    AwaitingShipment,
+    Shipped,
+    Delivered,
+    Disputed,
+    Refunded,
+}
+
+/// The reason the stock of a supply did not change
+#[derive(Clone, Debug, PartialEq)]
+pub enum StockError {
+    SupplyNotFound,
+    InsufficientStock { requested: u32, available: u32 },
+    Overflow { quantity: u32, added: u32 },
+}
+
+/// The reason a purchase did not end up with a transaction
+#[derive(Clone, Debug, PartialEq)]
+pub enum PurchaseError {
+    SupplyNotFound,
+    BuyerNotFound,
+    OutOfStock,
+    Rejected(PurchaseViolation),
+}
+
+impl From<StockError> for PurchaseError {
+    fn from(error: StockError) -> Self {
+        match error {
+            StockError::SupplyNotFound => PurchaseError::SupplyNotFound,
+            StockError::InsufficientStock { .. } | StockError::Overflow { .. } => PurchaseError::OutOfStock,
+        }
+    }
+}
+
+/// Where the funds held for a transaction of the market went
+pub(crate) type MarketSettlement<T> = Settlement<<T as MarketConfig>::BuyerId, <T as MarketConfig>::ProviderId, <T as MarketConfig>::MarketerId>;
+
 pub struct MarketState<T: MarketConfig> {
-    providers: HashMap<T::SupplyId, T::Provider>,
+    providers: HashMap<T::ProviderId, T::Provider>,
     marketers: HashMap<T::MarketerId, T::Marketer>,
     buyers: HashMap<T::BuyerId, T::Buyer>,
     supplies: HashMap<T::SupplyId, T::Supply>,
+    safeguards: PurchaseGuard<T::BuyerId, T::SupplyId>,
+    escrow: Escrow<T::BuyerId, T::ProviderId, T::MarketerId>,
+}
+
+impl<T: MarketConfig> Default for MarketState<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: MarketConfig> MarketState<T> {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             providers: HashMap::new(),
             marketers: HashMap::new(),
             buyers: HashMap::new(),
             supplies: HashMap::new(),
+            safeguards: PurchaseGuard::default(),
+            escrow: Escrow::default(),
         }
     }
 
-    fn buy(&mut self, buyer: &T::Buyer, ad: &T::Advertisement) -> Option<T::Transaction> {
-        let supply = match self.supplies.get_mut(&ad.supply) {
-            None => return None,
+    pub(crate) fn add_provider(&mut self, id: T::ProviderId, provider: T::Provider) {
+        self.providers.insert(id, provider);
+    }
+
+    pub(crate) fn add_marketer(&mut self, id: T::MarketerId, marketer: T::Marketer) {
+        self.marketers.insert(id, marketer);
+    }
+
+    pub(crate) fn add_buyer(&mut self, id: T::BuyerId, buyer: T::Buyer) {
+        self.buyers.insert(id, buyer);
+    }
+
+    pub(crate) fn add_supply(&mut self, id: T::SupplyId, supply: T::Supply) {
+        self.supplies.insert(id, supply);
+    }
+
+    pub(crate) fn has_marketer(&self, id: &T::MarketerId) -> bool {
+        self.marketers.contains_key(id)
+    }
+
+    pub(crate) fn providers(&self) -> impl Iterator<Item = &T::Provider> {
+        self.providers.values()
+    }
+
+    pub(crate) fn provider_mut(&mut self, id: &T::ProviderId) -> Option<&mut T::Provider> {
+        self.providers.get_mut(id)
+    }
+
+    pub(crate) fn supply(&self, id: &T::SupplyId) -> Option<&T::Supply> {
+        self.supplies.get(id)
+    }
+
+    pub(crate) fn supply_mut(&mut self, id: &T::SupplyId) -> Option<&mut T::Supply> {
+        self.supplies.get_mut(id)
+    }
+
+    pub(crate) fn set_purchase_policy(&mut self, policy: PurchasePolicy<T::BuyerId>) {
+        // only the rules change, the purchases made so far still count against them
+        *self.safeguards.policy_mut() = policy;
+    }
+
+    /// Changes the terms of the escrow, the funds already held in it get settled on the new ones
+    pub(crate) fn set_escrow(&mut self, release_timeout: Duration, marketer_commission: Commission) -> Result<(), EscrowError> {
+        self.escrow.set_terms(release_timeout, marketer_commission)
+    }
+
+    /// Buys a single item of the advertised supply, taking it out of the market's stock
+    pub(crate) fn buy(market: &mut T, buyer: &T::Buyer, ad: &T::Advertisement) -> Result<T::Transaction, PurchaseError> {
+        Self::buy_taking_stock(market, buyer, ad, |market, id| market.take_stock(id, 1))
+    }
+
+    /// Buys the advertised supply, with `take_stock` taking the item out of the stock and telling how many are left
+    pub(crate) fn buy_taking_stock<F>(
+        market: &mut T,
+        buyer: &T::Buyer,
+        ad: &T::Advertisement,
+        take_stock: F,
+    ) -> Result<T::Transaction, PurchaseError>
+    where
+        F: FnOnce(&mut T, &T::SupplyId) -> Result<u32, StockError>,
+    {
+        let (buyer_id, _) = Self::purchase(market, buyer, ad, take_stock)?;
+
+        Ok(T::Transaction::new(buyer_id, ad.to_owned()))
+    }
+
+    /// Buys the advertised supply, but keeps the funds in escrow until the delivery is settled
+    pub(crate) fn buy_in_escrow(
+        market: &mut T,
+        buyer: &T::Buyer,
+        ad: &T::Advertisement,
+        amount: Amount,
+    ) -> Result<T::Transaction, PurchaseError> {
+        let (buyer_id, provider_id) = Self::purchase(market, buyer, ad, |market, id| market.take_stock(id, 1))?;
+        let escrow_id = market.state_mut().escrow.deposit(
+            buyer_id.clone(),
+            provider_id,
+            ad.marketer().clone(),
+            amount,
+        );
+
+        Ok(T::Transaction::in_escrow(buyer_id, ad.to_owned(), escrow_id))
+    }
+
+    /// Buys items already taken out of the stock, i.e. reserved in a federation of markets,
+    /// so only the buyer and the safeguards are left to check
+    pub(crate) fn buy_reserved(&mut self, buyer: &T::Buyer, ad: &T::Advertisement, quantity: u32) -> Result<T::Transaction, PurchaseError> {
+        let buyer_id = match self.buyers.get(buyer.id()) {
+            None => return Err(PurchaseError::BuyerNotFound),
+            Some(value) => value.id().clone(),
+        };
+
+        self.safeguards
+            .authorize(&buyer_id, ad.supply(), quantity, Instant::now())
+            .map_err(PurchaseError::Rejected)?;
+
+        Ok(T::Transaction::new(buyer_id, ad.to_owned()))
+    }
+
+    /// Takes the advertised supply for the buyer, and tells who bought it from whom
+    fn purchase<F>(
+        market: &mut T,
+        buyer: &T::Buyer,
+        ad: &T::Advertisement,
+        take_stock: F,
+    ) -> Result<(T::BuyerId, T::ProviderId), PurchaseError>
+    where
+        F: FnOnce(&mut T, &T::SupplyId) -> Result<u32, StockError>,
+    {
+        let state = market.state();
+
+        let supply = match state.supplies.get(ad.supply()) {
+            None => return Err(PurchaseError::SupplyNotFound),
             Some(value) => value,
         };
 
-        let buyer = match self.buyers.get(&buyer.id) {
-            None => return None,
+        if !supply.has_supply_available() {
+            return Err(PurchaseError::OutOfStock);
+        }
+
+        let buyer = match state.buyers.get(buyer.id()) {
+            None => return Err(PurchaseError::BuyerNotFound),
             Some(value) => value,
         };
 
-        supply.set_state(T::SupplyState::Consumed);
+        let buyer_id = buyer.id().clone();
+        let provider_id = supply.provided_by().clone();
+        let now = Instant::now();
+
+        // a single purchase takes a single item of the supply
+        state.safeguards
+            .check(&buyer_id, ad.supply(), 1, now)
+            .map_err(PurchaseError::Rejected)?;
 
-        Some(T::Transaction::new(buyer.id.clone(), ad.to_owned()))
+        let remaining = take_stock(market, ad.supply())?;
+        let state = market.state_mut();
+
+        if remaining == 0 {
+            if let Some(supply) = state.supplies.get_mut(ad.supply()) {
+                supply.set_state(SupplyState::Consumed);
+            }
+        }
+
+        // only once the item got taken, so a failed purchase does not count against the limits
+        state.safeguards.record(&buyer_id, ad.supply(), 1, now);
+
+        Ok((buyer_id, provider_id))
+    }
+
+    pub(crate) fn ship(&mut self, transaction: &mut T::Transaction) -> Result<(), EscrowError> {
+        let escrow_id = transaction.escrow().ok_or(EscrowError::NotInEscrow)?;
+
+        self.escrow.mark_shipped(escrow_id, Instant::now())?;
+        transaction.set_state(DeliveryState::Shipped);
+
+        Ok(())
+    }
+
+    pub(crate) fn confirm_delivery(&mut self, transaction: &mut T::Transaction) -> Result<MarketSettlement<T>, EscrowError> {
+        let escrow_id = transaction.escrow().ok_or(EscrowError::NotInEscrow)?;
+        let settlement = self.escrow.confirm_delivery(escrow_id)?;
+
+        transaction.set_state(DeliveryState::Delivered);
+
+        Ok(settlement)
+    }
+
+    pub(crate) fn open_dispute(&mut self, transaction: &mut T::Transaction) -> Result<(), EscrowError> {
+        let escrow_id = transaction.escrow().ok_or(EscrowError::NotInEscrow)?;
+
+        self.escrow.open_dispute(escrow_id)?;
+        transaction.set_state(DeliveryState::Disputed);
+
+        Ok(())
+    }
+
+    pub(crate) fn resolve_dispute(
+        &mut self,
+        transaction: &mut T::Transaction,
+        resolution: DisputeResolution,
+    ) -> Result<MarketSettlement<T>, EscrowError> {
+        let escrow_id = transaction.escrow().ok_or(EscrowError::NotInEscrow)?;
+        let settlement = self.escrow.resolve_dispute(escrow_id, resolution)?;
+
+        transaction.set_state(match resolution {
+            DisputeResolution::RefundBuyer => DeliveryState::Refunded,
+            DisputeResolution::ReleaseToProvider => DeliveryState::Delivered,
+        });
+
+        Ok(settlement)
     }
 
-    fn advertise(&self, supply: &mut T::Supply) -> Option<T::Advertisement> {
-        if self.marketers.get(&T::MarketerId("m1".into())).is_none() {
+    /// Releases the funds when the buyer did not react in time after the shipment
+    pub(crate) fn settle_if_expired(&mut self, transaction: &mut T::Transaction, now: Instant) -> Option<MarketSettlement<T>> {
+        let escrow_id = transaction.escrow()?;
+
+        if !self.escrow.is_expired(escrow_id, now) {
             return None;
-        };
+        }
+
+        let settlement = self.escrow.confirm_delivery(escrow_id).ok()?;
+
+        transaction.set_state(DeliveryState::Delivered);
+
+        Some(settlement)
+    }
+
+    /// Releases the funds of every shipment not confirmed nor disputed in time, the transactions
+    /// holding them catch up with `sync_delivery`
+    pub(crate) fn release_expired(&mut self, now: Instant) -> Vec<(EscrowId, MarketSettlement<T>)> {
+        self.escrow.release_expired(now)
+    }
+
+    /// Brings the delivery state of the transaction in line with its escrow entry
+    pub(crate) fn sync_delivery(&self, transaction: &mut T::Transaction) -> Result<(), EscrowError> {
+        let escrow_id = transaction.escrow().ok_or(EscrowError::NotInEscrow)?;
+        let entry = self.escrow.get(escrow_id).ok_or(EscrowError::NotFound(escrow_id))?;
+
+        transaction.set_state(match entry.status {
+            EscrowStatus::Held => DeliveryState::AwaitingShipment,
+            EscrowStatus::Shipped { .. } => DeliveryState::Shipped,
+            EscrowStatus::Disputed => DeliveryState::Disputed,
+            EscrowStatus::Released => DeliveryState::Delivered,
+            EscrowStatus::Refunded => DeliveryState::Refunded,
+        });
+
+        Ok(())
+    }
+
+    pub(crate) fn advertise(&mut self, marketer_id: &T::MarketerId, supply_id: &T::SupplyId) -> Option<T::Advertisement> {
+        if !self.marketers.contains_key(marketer_id) {
+            return None;
+        }
+
+        let supply = self.supplies.get_mut(supply_id)?;
 
-        if supply.has_supply_available() == false {
+        if !supply.has_supply_available() {
             return None;
         }
 
         // make the state transition to be exectued
-        supply.set_state(T::SupplyState::Marketed);
+        supply.set_state(SupplyState::Marketed);
+
+        Some(T::Advertisement::new(marketer_id.clone(), supply_id.clone()))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::test_market::{Ad, Buyer, BuyerId, Marketer, MarketerId, MyTestMarket, Provider, ProviderId, SupplyId};
+
+    fn market() -> (MyTestMarket, Buyer, Ad) {
+        let mut market = MyTestMarket::default();
+        let provider = Provider::new();
+        let marketer = Marketer::new();
+        let buyer = Buyer::new("mr buyer".into());
+        let supply_id = SupplyId("amber".into());
+
+        market.state.add_supply(supply_id.clone(), provider.creates_supply("amber".into(), 3));
+        market.state.add_provider(provider.id.clone(), provider);
+        market.state.add_marketer(marketer.id.clone(), marketer.clone());
+        market.state.add_buyer(buyer.id.clone(), buyer.clone());
+
+        // the marketer takes 10%
+        market.state.set_escrow(Duration::from_secs(60), 1_000).unwrap();
+
+        let ad = market.state.advertise(&marketer.id, &supply_id).unwrap();
+
+        (market, buyer, ad)
+    }
+
+    fn released(amount: Amount) -> MarketSettlement<MyTestMarket> {
+        Settlement::Released {
+            provider: ProviderId("p1".into()),
+            provider_amount: amount - amount / 10,
+            marketer: MarketerId("m1".into()),
+            marketer_amount: amount / 10,
+        }
+    }
+
+    #[test]
+    fn it_settles_the_escrow_once_the_delivery_is_confirmed() {
+        let (mut market, buyer, ad) = market();
+        let mut transaction = MarketState::buy_in_escrow(&mut market, &buyer, &ad, 100).unwrap();
 
-        Some(T::Advertisement::new(self.id.clone(), supply.id.clone()))
+        assert_eq!(transaction.delivery, DeliveryState::AwaitingShipment);
+        assert_eq!(market.stock(&ad.supply), Some(2));
+
+        market.state.ship(&mut transaction).unwrap();
+
+        assert_eq!(transaction.delivery, DeliveryState::Shipped);
+        assert_eq!(market.state.confirm_delivery(&mut transaction), Ok(released(100)));
+        assert_eq!(transaction.delivery, DeliveryState::Delivered);
+        assert_eq!(
+            market.state.confirm_delivery(&mut transaction),
+            Err(EscrowError::InvalidStatus(EscrowStatus::Released))
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_refunds_the_buyer_when_the_dispute_is_resolved_in_their_favour() {
+        let (mut market, buyer, ad) = market();
+        let mut transaction = MarketState::buy_in_escrow(&mut market, &buyer, &ad, 100).unwrap();
+
+        market.state.ship(&mut transaction).unwrap();
+        market.state.open_dispute(&mut transaction).unwrap();
+
+        assert_eq!(transaction.delivery, DeliveryState::Disputed);
+        assert_eq!(
+            market.state.resolve_dispute(&mut transaction, DisputeResolution::RefundBuyer),
+            Ok(Settlement::Refunded {
+                buyer: BuyerId("b1".into()),
+                amount: 100,
+            })
+        );
+        assert_eq!(transaction.delivery, DeliveryState::Refunded);
+    }
+
+    #[test]
+    fn it_releases_the_funds_when_the_buyer_does_not_react_in_time() {
+        let (mut market, buyer, ad) = market();
+        let mut settled = MarketState::buy_in_escrow(&mut market, &buyer, &ad, 100).unwrap();
+        let mut swept = MarketState::buy_in_escrow(&mut market, &buyer, &ad, 50).unwrap();
+
+        market.state.ship(&mut settled).unwrap();
+        market.state.ship(&mut swept).unwrap();
+
+        let now = Instant::now();
+        let timed_out = now + Duration::from_secs(60);
+
+        assert_eq!(market.state.settle_if_expired(&mut settled, now), None);
+        assert_eq!(market.state.settle_if_expired(&mut settled, timed_out), Some(released(100)));
+        assert_eq!(settled.delivery, DeliveryState::Delivered);
+        assert_eq!(market.state.release_expired(timed_out), [(swept.escrow.unwrap(), released(50))]);
+        assert_eq!(swept.delivery, DeliveryState::Shipped);
+
+        market.state.sync_delivery(&mut swept).unwrap();
+
+        assert_eq!(swept.delivery, DeliveryState::Delivered);
+    }
+
+    #[test]
+    fn it_rejects_escrow_terms_with_commission_above_the_whole_amount() {
+        let (mut market, buyer, ad) = market();
+        let mut transaction = MarketState::buy(&mut market, &buyer, &ad).unwrap();
+
+        assert_eq!(
+            market.state.set_escrow(Duration::from_secs(60), 10_001),
+            Err(EscrowError::InvalidCommission(10_001))
+        );
+        assert_eq!(market.state.ship(&mut transaction), Err(EscrowError::NotInEscrow));
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Limits how many purchases a buyer can make within a time window
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub max_purchases: u32,
+    pub window: Duration,
+}
+
+/// Limits how many items a buyer can take, across all supplies, within a time window
+#[derive(Clone, Debug, PartialEq)]
+pub struct VelocityLimit {
+    pub max_items: u32,
+    pub window: Duration,
+}
+
+/// Set of rules every purchase on the market has to comply with
+#[derive(Clone, Debug)]
+pub struct PurchasePolicy<B> {
+    /// How many items of a single supply one buyer can ever take
+    pub max_items_per_supply: Option<u32>,
+    pub rate_limit: Option<RateLimit>,
+    pub velocity_limit: Option<VelocityLimit>,
+    pub blocklist: HashSet<B>,
+}
+
+impl<B> Default for PurchasePolicy<B> {
+    fn default() -> Self {
+        Self {
+            max_items_per_supply: None,
+            rate_limit: None,
+            velocity_limit: None,
+            blocklist: HashSet::new(),
+        }
+    }
+}
+
+/// The reason a purchase got rejected
+#[derive(Clone, Debug, PartialEq)]
+pub enum PurchaseViolation {
+    Blocklisted,
+    SupplyLimitExceeded { limit: u32, already_purchased: u32 },
+    RateLimitExceeded { limit: u32, window: Duration },
+    VelocityExceeded { limit: u32, window: Duration },
+}
+
+impl Display for PurchaseViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PurchaseViolation::Blocklisted => write!(f, "buyer is blocklisted"),
+            PurchaseViolation::SupplyLimitExceeded { limit, already_purchased } => write!(
+                f,
+                "buyer already purchased {} of {} items allowed for this supply",
+                already_purchased, limit
+            ),
+            PurchaseViolation::RateLimitExceeded { limit, window } => write!(
+                f,
+                "buyer exceeded {} purchases within {:?}",
+                limit, window
+            ),
+            PurchaseViolation::VelocityExceeded { limit, window } => write!(
+                f,
+                "buyer exceeded {} items within {:?}",
+                limit, window
+            ),
+        }
+    }
+}
+
+impl Error for PurchaseViolation {}
+
+#[derive(Debug)]
+struct PurchaseRecord {
+    items: u32,
+    at: Instant,
+}
+
+/// Keeps track of buyers' purchases and checks them against the `PurchasePolicy`
+#[derive(Debug)]
+pub struct PurchaseGuard<B, S> {
+    policy: PurchasePolicy<B>,
+    /// Recent purchases: only the ones still relevant for the time windows
+    recent: HashMap<B, VecDeque<PurchaseRecord>>,
+    /// Items taken by a buyer, per supply
+    totals: HashMap<(B, S), u32>,
+}
+
+impl<B, S> Default for PurchaseGuard<B, S> {
+    fn default() -> Self {
+        Self {
+            policy: PurchasePolicy::default(),
+            recent: HashMap::new(),
+            totals: HashMap::new(),
+        }
+    }
+}
+
+impl<B: Clone + Eq + Hash, S: Clone + Eq + Hash> PurchaseGuard<B, S> {
+    pub fn new(policy: PurchasePolicy<B>) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn policy(&self) -> &PurchasePolicy<B> {
+        &self.policy
+    }
+
+    pub fn policy_mut(&mut self) -> &mut PurchasePolicy<B> {
+        &mut self.policy
+    }
+
+    /// Checks if the buyer is allowed to take `items` of the supply at the `now` moment
+    pub fn check(
+        &self,
+        buyer: &B,
+        supply: &S,
+        items: u32,
+        now: Instant,
+    ) -> Result<(), PurchaseViolation> {
+        if self.policy.blocklist.contains(buyer) {
+            return Err(PurchaseViolation::Blocklisted);
+        }
+
+        if let Some(limit) = self.policy.max_items_per_supply {
+            let already_purchased = self
+                .totals
+                .get(&(buyer.clone(), supply.clone()))
+                .copied()
+                .unwrap_or(0);
+
+            if already_purchased.saturating_add(items) > limit {
+                return Err(PurchaseViolation::SupplyLimitExceeded {
+                    limit,
+                    already_purchased,
+                });
+            }
+        }
+
+        if let Some(rate_limit) = &self.policy.rate_limit {
+            let purchases = self.recent_purchases(buyer, rate_limit.window, now).count();
+
+            if purchases >= rate_limit.max_purchases as usize {
+                return Err(PurchaseViolation::RateLimitExceeded {
+                    limit: rate_limit.max_purchases,
+                    window: rate_limit.window,
+                });
+            }
+        }
+
+        if let Some(velocity_limit) = &self.policy.velocity_limit {
+            let recent_items: u32 = self
+                .recent_purchases(buyer, velocity_limit.window, now)
+                .map(|record| record.items)
+                .sum();
+
+            if recent_items.saturating_add(items) > velocity_limit.max_items {
+                return Err(PurchaseViolation::VelocityExceeded {
+                    limit: velocity_limit.max_items,
+                    window: velocity_limit.window,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Notes down the purchase, so it counts against the limits from now on
+    pub fn record(&mut self, buyer: &B, supply: &S, items: u32, now: Instant) {
+        let total = self
+            .totals
+            .entry((buyer.clone(), supply.clone()))
+            .or_insert(0);
+        *total = total.saturating_add(items);
+
+        let longest_window = self.longest_window();
+        let records = self.recent.entry(buyer.clone()).or_default();
+
+        // forget purchases that no time window can reach anymore
+        while let Some(oldest) = records.front() {
+            match longest_window {
+                Some(window) if now.saturating_duration_since(oldest.at) < window => break,
+                _ => {
+                    records.pop_front();
+                }
+            }
+        }
+
+        if longest_window.is_some() {
+            records.push_back(PurchaseRecord { items, at: now });
+        }
+    }
+
+    /// Checks the purchase and, when it's allowed, records it right away
+    pub fn authorize(
+        &mut self,
+        buyer: &B,
+        supply: &S,
+        items: u32,
+        now: Instant,
+    ) -> Result<(), PurchaseViolation> {
+        self.check(buyer, supply, items, now)?;
+        self.record(buyer, supply, items, now);
+
+        Ok(())
+    }
+
+    fn recent_purchases<'a>(
+        &'a self,
+        buyer: &B,
+        window: Duration,
+        now: Instant,
+    ) -> impl Iterator<Item = &'a PurchaseRecord> + 'a {
+        self.recent
+            .get(buyer)
+            .into_iter()
+            .flatten()
+            .filter(move |record| now.saturating_duration_since(record.at) < window)
+    }
+
+    fn longest_window(&self) -> Option<Duration> {
+        let rate_window = self.policy.rate_limit.as_ref().map(|limit| limit.window);
+        let velocity_window = self.policy.velocity_limit.as_ref().map(|limit| limit.window);
+
+        rate_window.max(velocity_window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(policy: PurchasePolicy<String>) -> PurchaseGuard<String, String> {
+        PurchaseGuard::new(policy)
+    }
+
+    #[test]
+    fn it_rejects_blocklisted_buyers() {
+        let mut policy = PurchasePolicy::default();
+        policy.blocklist.insert("b1".to_string());
+
+        let mut guard = guard(policy);
+        let now = Instant::now();
+
+        assert_eq!(
+            guard.authorize(&"b1".into(), &"s1".into(), 1, now),
+            Err(PurchaseViolation::Blocklisted)
+        );
+        assert_eq!(guard.authorize(&"b2".into(), &"s1".into(), 1, now), Ok(()));
+    }
+
+    #[test]
+    fn it_limits_items_per_supply() {
+        let mut guard = guard(PurchasePolicy {
+            max_items_per_supply: Some(2),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        let buyer = "b1".to_string();
+
+        assert_eq!(guard.authorize(&buyer, &"s1".into(), 2, now), Ok(()));
+        assert_eq!(
+            guard.authorize(&buyer, &"s1".into(), 1, now),
+            Err(PurchaseViolation::SupplyLimitExceeded {
+                limit: 2,
+                already_purchased: 2
+            })
+        );
+        // other supplies are not affected
+        assert_eq!(guard.authorize(&buyer, &"s2".into(), 1, now), Ok(()));
+    }
+
+    #[test]
+    fn it_limits_purchases_rate_within_time_window() {
+        let window = Duration::from_secs(60);
+        let mut guard = guard(PurchasePolicy {
+            rate_limit: Some(RateLimit {
+                max_purchases: 2,
+                window,
+            }),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let buyer = "b1".to_string();
+
+        assert_eq!(guard.authorize(&buyer, &"s1".into(), 1, start), Ok(()));
+        assert_eq!(guard.authorize(&buyer, &"s2".into(), 1, start), Ok(()));
+        assert_eq!(
+            guard.authorize(&buyer, &"s3".into(), 1, start + Duration::from_secs(30)),
+            Err(PurchaseViolation::RateLimitExceeded {
+                limit: 2,
+                window
+            })
+        );
+        // the window has passed, so the buyer can purchase again
+        assert_eq!(
+            guard.authorize(&buyer, &"s3".into(), 1, start + window),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn it_checks_velocity_of_items_taken() {
+        let window = Duration::from_secs(10);
+        let mut guard = guard(PurchasePolicy {
+            velocity_limit: Some(VelocityLimit {
+                max_items: 5,
+                window,
+            }),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let buyer = "b1".to_string();
+
+        assert_eq!(guard.authorize(&buyer, &"s1".into(), 3, start), Ok(()));
+        assert_eq!(
+            guard.authorize(&buyer, &"s2".into(), 3, start),
+            Err(PurchaseViolation::VelocityExceeded {
+                limit: 5,
+                window
+            })
+        );
+        assert_eq!(guard.authorize(&buyer, &"s2".into(), 2, start), Ok(()));
+        assert_eq!(
+            guard.authorize(&buyer, &"s3".into(), 5, start + window),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use crate::market::{Identified, MarketAd, MarketConfig, MarketState, MarketSupply, MarketTransaction, StockError, SupplyState, DeliveryState, UpdateState };
+use crate::escrow::EscrowId;
+use std::borrow::BorrowMut;
+
+#[derive(Default)]
+pub(crate) struct MyTestMarket {
+    pub(crate) state: MarketState<MyTestMarket>,
+}
+
+impl MarketConfig for MyTestMarket {
+    type ProviderId = ProviderId;
+    type Provider = Provider;
+    type MarketerId = MarketerId;
+    type Marketer = Marketer;
+    type BuyerId = BuyerId;
+    type Buyer = Buyer;
+    type SupplyId = SupplyId;
+    type Supply = Supply;
+    type Transaction = Transaction;
+    type Advertisement = Ad;
+
+    fn state(&self) -> &MarketState<Self> {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut MarketState<Self> {
+        self.state.borrow_mut()
+    }
+
+    fn stock(&self, supply: &SupplyId) -> Option<u32> {
+        self.state.supply(supply).map(|supply| supply.available_items)
+    }
+
+    fn take_stock(&mut self, supply: &SupplyId, quantity: u32) -> Result<u32, StockError> {
+        let supply = self.state.supply_mut(supply).ok_or(StockError::SupplyNotFound)?;
+
+        supply.available_items = supply.available_items.checked_sub(quantity).ok_or(StockError::InsufficientStock {
+            requested: quantity,
+            available: supply.available_items,
+        })?;
+
+        Ok(supply.available_items)
+    }
+
+    fn return_stock(&mut self, supply: &SupplyId, quantity: u32) -> Result<u32, StockError> {
+        let supply = self.state.supply_mut(supply).ok_or(StockError::SupplyNotFound)?;
+
+        supply.available_items = supply.available_items.checked_add(quantity).ok_or(StockError::Overflow {
+            quantity: supply.available_items,
+            added: quantity,
+        })?;
+
+        Ok(supply.available_items)
+    }
+}
+
+#[derive(Clone,Debug,Hash,PartialEq,Eq)]
+pub(crate) struct ProviderId(pub(crate) String);
+
+#[derive(Clone)]
+pub(crate) struct Provider {
+    pub(crate) id: ProviderId,
+    #[allow(dead_code)]
+    pub(crate) name: String,
+}
+
+impl Provider {
+    pub(crate) fn new() -> Self {
+        Self {
+            id: ProviderId("p1".into()),
+            name: "Provider name".into(),
+        }
+    }
+
+    pub(crate) fn creates_supply(&self, name: String, available_items: u32) -> Supply {
+        Supply::new(self.id.clone(), name, available_items)
+    }
+}
+
+#[derive(Clone,Debug,Hash,PartialEq,Eq)]
+pub(crate) struct MarketerId(pub(crate) String);
+
+#[derive(Clone)]
+pub(crate) struct Marketer {
+    pub(crate) id: MarketerId,
+    #[allow(dead_code)]
+    pub(crate) name: String,
+}
+
+impl Marketer {
+    pub(crate) fn new() -> Self {
+        Marketer {
+            id: MarketerId("m1".into()),
+            name: "Marketer name".into(),
+        }
+    }
+}
+
+#[derive(Clone,Debug,Hash,PartialEq,Eq)]
+pub(crate) struct BuyerId(pub(crate) String);
+
+#[derive(Clone)]
+pub(crate) struct Buyer {
+    pub(crate) id: BuyerId,
+    #[allow(dead_code)]
+    pub(crate) name: String,
+}
+
+impl Buyer {
+    pub(crate) fn new(name: String) -> Self {
+        Buyer {
+            id: BuyerId("b1".into()),
+            name,
+        }
+    }
+}
+
+impl Identified<BuyerId> for Buyer {
+    fn id(&self) -> &BuyerId {
+        &self.id
+    }
+}
+
+pub(crate) type AvailableSupply = u32;
+
+#[derive(Clone,Debug,Hash,PartialEq,Eq)]
+pub(crate) struct SupplyId(pub(crate) String);
+
+#[derive(Clone,Debug,Hash,PartialEq,Eq)]
+pub(crate) struct Supply {
+    pub(crate) id: SupplyId,
+    pub(crate) provided_by: ProviderId,
+    pub(crate) name: String,
+    pub(crate) available_items: AvailableSupply,
+    pub(crate) state: SupplyState,
+}
+
+impl Supply {
+    pub(crate) fn new(provider_id: ProviderId, name: String, available_items: AvailableSupply) -> Self {
+        Self {
+            name,
+            available_items,
+            provided_by: provider_id,
+            state: SupplyState::Created,
+            id: SupplyId("s".into()),
+        }
+    }
+
+}
+
+impl MarketSupply<ProviderId> for Supply {
+    fn provided_by(&self) -> &ProviderId {
+        &self.provided_by
+    }
+
+    fn has_supply_available(&self) -> bool {
+        self.available_items > 0
+    }
+}
+
+impl UpdateState<SupplyState> for Supply {
+    fn set_state(&mut self, state: SupplyState) {
+        self.state = state;
+    }
+}
+
+
+#[derive(Clone,Debug,PartialEq)]
+pub(crate) struct Ad {
+    pub(crate) marketer: MarketerId,
+    pub(crate) supply: SupplyId,
+}
+
+impl MarketAd<MarketerId, SupplyId> for Ad {
+    fn new(marketer_id: MarketerId, supply_id: SupplyId) -> Self {
+        Self {
+            marketer: marketer_id,
+            supply: supply_id,
+        }
+    }
+
+    fn marketer(&self) -> &MarketerId {
+        &self.marketer
+    }
+
+    fn supply(&self) -> &SupplyId {
+        &self.supply
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Transaction {
+    pub(crate) ad: Ad,
+    pub(crate) taker: BuyerId,
+    pub(crate) escrow: Option<EscrowId>,
+    pub(crate) delivery: DeliveryState,
+}
+
+impl MarketTransaction<BuyerId, Ad> for Transaction {
+    fn new(buyer_id: BuyerId, ad: Ad) -> Self {
+        Self {
+            ad,
+            taker: buyer_id,
+            escrow: None,
+            delivery: DeliveryState::Delivered,
+        }
+    }
+
+    fn in_escrow(buyer_id: BuyerId, ad: Ad, escrow_id: EscrowId) -> Self {
+        Self {
+            ad,
+            taker: buyer_id,
+            escrow: Some(escrow_id),
+            delivery: DeliveryState::AwaitingShipment,
+        }
+    }
+
+    fn escrow(&self) -> Option<EscrowId> {
+        self.escrow
+    }
+}
+
+impl UpdateState<DeliveryState> for Transaction {
+    fn set_state(&mut self, state: DeliveryState) {
+        self.delivery = state;
+    }
+}